@@ -0,0 +1,249 @@
+//! Call hierarchies, and a tree model whose nodes are expanded on demand.
+use languageserver_types::{Range, SymbolKind};
+use serde_json as json;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CallHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: String,
+    pub range: Range,
+    #[serde(rename = "selectionRange")]
+    pub selection_range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<json::Value>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CallHierarchyIncomingCallsParams {
+    pub item: CallHierarchyItem,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CallHierarchyOutgoingCallsParams {
+    pub item: CallHierarchyItem,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CallHierarchyIncomingCall {
+    pub from: CallHierarchyItem,
+    /// The ranges of the calls, in the caller.
+    #[serde(rename = "fromRanges")]
+    pub from_ranges: Vec<Range>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CallHierarchyOutgoingCall {
+    pub to: CallHierarchyItem,
+    /// The ranges of the calls, in the item the outgoing calls were requested for.
+    #[serde(rename = "fromRanges")]
+    pub from_ranges: Vec<Range>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CallDirection {
+    /// Children are the callers of their parent.
+    Incoming,
+    /// Children are the functions called by their parent.
+    Outgoing,
+}
+
+/// Identifies a hierarchy item across requests, since servers return new (but equal) items
+/// each time.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ItemKey {
+    uri: String,
+    line: u64,
+    character: u64,
+    name: String,
+}
+
+impl ItemKey {
+    pub fn new(uri: &str, selection_range: &Range, name: &str) -> Self {
+        ItemKey {
+            uri: uri.to_string(),
+            line: selection_range.start.line,
+            character: selection_range.start.character,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl<'a> From<&'a CallHierarchyItem> for ItemKey {
+    fn from(item: &'a CallHierarchyItem) -> Self {
+        ItemKey::new(&item.uri, &item.selection_range, &item.name)
+    }
+}
+
+pub type NodeId = usize;
+
+#[derive(Clone, Debug)]
+pub struct CallNode {
+    pub item: CallHierarchyItem,
+    /// The ranges of the calls linking this node to its parent.
+    pub call_ranges: Vec<Range>,
+    pub parent: Option<NodeId>,
+    /// Set when the item is already one of the node's ancestors. Such nodes are never expanded.
+    pub recursive: bool,
+    children: Option<Vec<NodeId>>,
+}
+
+/// A call hierarchy whose nodes are expanded on demand. The calls of each item are cached, so
+/// that an item appearing in several places of the tree is only requested once.
+pub struct CallHierarchyTree {
+    direction: CallDirection,
+    nodes: Vec<CallNode>,
+    roots: Vec<NodeId>,
+    calls: HashMap<ItemKey, Vec<(CallHierarchyItem, Vec<Range>)>>,
+}
+
+impl CallHierarchyTree {
+    pub fn new(direction: CallDirection, roots: Vec<CallHierarchyItem>) -> Self {
+        let mut tree = CallHierarchyTree {
+            direction: direction,
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            calls: HashMap::new(),
+        };
+        for item in roots {
+            let id = tree.push_node(item, Vec::new(), None);
+            tree.roots.push(id);
+        }
+        tree
+    }
+
+    pub fn direction(&self) -> CallDirection {
+        self.direction
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn node(&self, id: NodeId) -> &CallNode {
+        &self.nodes[id]
+    }
+
+    /// The children of a node, or `None` if it has not been expanded yet.
+    pub fn children(&self, id: NodeId) -> Option<&[NodeId]> {
+        self.nodes[id].children.as_ref().map(|children| children.as_slice())
+    }
+
+    pub fn is_expanded(&self, id: NodeId) -> bool {
+        self.nodes[id].children.is_some()
+    }
+
+    /// Whether expanding the node requires a request to the server.
+    pub fn needs_request(&self, id: NodeId) -> bool {
+        let node = &self.nodes[id];
+        !node.recursive && node.children.is_none() &&
+        !self.calls.contains_key(&ItemKey::from(&node.item))
+    }
+
+    /// Expand a node from the cached calls of its item, if there are any. Recursive nodes are
+    /// expanded with no children.
+    pub fn expand_cached(&mut self, id: NodeId) -> Option<&[NodeId]> {
+        if self.nodes[id].recursive {
+            self.nodes[id].children = Some(Vec::new());
+        }
+        if self.nodes[id].children.is_none() {
+            let calls = match self.calls.get(&ItemKey::from(&self.nodes[id].item)) {
+                Some(calls) => calls.clone(),
+                None => return None,
+            };
+            self.add_children(id, calls);
+        }
+        self.children(id)
+    }
+
+    /// Expand a node with the calls returned by the server for its item.
+    pub fn expand_with(&mut self, id: NodeId, calls: Vec<(CallHierarchyItem, Vec<Range>)>) -> &[NodeId] {
+        self.calls.insert(ItemKey::from(&self.nodes[id].item), calls.clone());
+        if self.nodes[id].children.is_none() {
+            self.add_children(id, calls);
+        }
+        self.children(id).unwrap_or(&[])
+    }
+
+    fn add_children(&mut self, id: NodeId, calls: Vec<(CallHierarchyItem, Vec<Range>)>) {
+        let children = calls.into_iter()
+            .map(|(item, ranges)| self.push_node(item, ranges, Some(id)))
+            .collect();
+        self.nodes[id].children = Some(children);
+    }
+
+    fn push_node(&mut self, item: CallHierarchyItem, call_ranges: Vec<Range>, parent: Option<NodeId>) -> NodeId {
+        let key = ItemKey::from(&item);
+        let mut recursive = false;
+        let mut ancestor = parent;
+        while let Some(ancestor_id) = ancestor {
+            if ItemKey::from(&self.nodes[ancestor_id].item) == key {
+                recursive = true;
+                break;
+            }
+            ancestor = self.nodes[ancestor_id].parent;
+        }
+
+        self.nodes.push(CallNode {
+            item: item,
+            call_ranges: call_ranges,
+            parent: parent,
+            recursive: recursive,
+            children: None,
+        });
+        self.nodes.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::{Position, Range, SymbolKind};
+
+    fn item(name: &str, line: u64) -> CallHierarchyItem {
+        let range = Range {
+            start: Position { line: line, character: 0 },
+            end: Position { line: line, character: 10 },
+        };
+        CallHierarchyItem {
+            name: name.to_string(),
+            kind: SymbolKind::Function,
+            detail: None,
+            uri: "file:///main.go".to_string(),
+            range: range.clone(),
+            selection_range: range,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn recursive_calls_are_not_expanded() {
+        let mut tree = CallHierarchyTree::new(CallDirection::Outgoing, vec![item("even", 1)]);
+        let root = tree.roots()[0];
+        assert!(tree.needs_request(root));
+
+        let odd = tree.expand_with(root, vec![(item("odd", 5), vec![])])[0];
+        let even = tree.expand_with(odd, vec![(item("even", 1), vec![])])[0];
+
+        assert!(tree.node(even).recursive);
+        assert!(!tree.needs_request(even));
+        assert_eq!(tree.expand_cached(even), Some(&[][..]));
+    }
+
+    #[test]
+    fn calls_are_cached_per_item() {
+        let mut tree = CallHierarchyTree::new(CallDirection::Incoming,
+                                              vec![item("a", 1), item("b", 2)]);
+        let (a, b) = (tree.roots()[0], tree.roots()[1]);
+        let shared = tree.expand_with(a, vec![(item("shared", 3), vec![])])[0];
+        tree.expand_with(shared, vec![(item("main", 9), vec![])]);
+        let other_shared = tree.expand_with(b, vec![(item("shared", 3), vec![])])[0];
+
+        assert!(!tree.needs_request(other_shared));
+        let children = tree.expand_cached(other_shared).unwrap().to_vec();
+        assert_eq!(tree.node(children[0]).item.name, "main");
+    }
+}
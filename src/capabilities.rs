@@ -0,0 +1,380 @@
+use code_actions::kinds;
+use semantic_tokens::{TOKEN_MODIFIERS, TOKEN_TYPES};
+use serde_json as json;
+use serde_json::builder::ObjectBuilder;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The raw `capabilities` object sent by the server in its initialize response.
+///
+/// It is kept as JSON rather than `ServerCapabilities` so that capabilities introduced after
+/// the version of the protocol modeled in `languageserver_types` can still be looked up.
+#[derive(Clone)]
+pub struct Capabilities {
+    inner: Rc<RefCell<Option<json::Value>>>,
+    /// The capabilities registered before the server answered the initialize request, by
+    /// provider key. They are added once it did.
+    pending: Rc<RefCell<Vec<(&'static str, json::Value)>>>,
+}
+
+impl Capabilities {
+    pub fn new() -> Self {
+        Capabilities {
+            inner: Rc::new(RefCell::new(None)),
+            pending: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn set(&self, capabilities: Option<json::Value>) {
+        *self.inner.borrow_mut() = capabilities;
+        if self.inner.borrow().is_some() {
+            for (key, options) in self.pending.borrow_mut().drain(..) {
+                self.insert(key, options);
+            }
+        }
+    }
+
+    pub fn get(&self) -> Option<json::Value> {
+        self.inner.borrow().clone()
+    }
+
+    /// Looks up a capability by JSON pointer, e.g. `/executeCommandProvider/commands`.
+    pub fn lookup(&self, pointer: &str) -> Option<json::Value> {
+        self.inner.borrow().as_ref().and_then(|caps| caps.pointer(pointer).cloned())
+    }
+
+    /// Providers can be advertised as `true` or as an options object, and disabled with
+    /// `false`, `null` or by being absent.
+    pub fn is_enabled(&self, pointer: &str) -> bool {
+        match self.lookup(pointer) {
+            None |
+            Some(json::Value::Null) |
+            Some(json::Value::Bool(false)) => false,
+            Some(_) => true,
+        }
+    }
+
+    /// Records a capability registered dynamically with `client/registerCapability`, as if it
+    /// had been advertised at initialization. Registrations received before the initialize
+    /// response are queued until then, so that the server does not look initialized. Returns
+    /// `false` for methods without a known capability.
+    pub fn register(&self, method: &str, options: json::Value) -> bool {
+        let key = match provider_key(method) {
+            Some(key) => key,
+            None => return false,
+        };
+        let options = match options {
+            json::Value::Null => json::Value::Bool(true),
+            options => options,
+        };
+        if self.inner.borrow().is_none() {
+            self.pending.borrow_mut().push((key, options));
+        } else {
+            self.insert(key, options);
+        }
+        true
+    }
+
+    fn insert(&self, key: &str, options: json::Value) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.as_ref().map_or(true, |caps| !caps.is_object()) {
+            *inner = Some(ObjectBuilder::new().build());
+        }
+        if let Some(caps) = inner.as_mut().and_then(|caps| caps.as_object_mut()) {
+            caps.insert(key.to_string(), options);
+        }
+    }
+
+    pub fn unregister(&self, method: &str) {
+        if let Some(key) = provider_key(method) {
+            self.pending.borrow_mut().retain(|&(pending, _)| pending != key);
+            if let Some(caps) = self.inner.borrow_mut().as_mut().and_then(|caps| caps.as_object_mut()) {
+                caps.remove(key);
+            }
+        }
+    }
+
+    pub fn supports_command(&self, command: &str) -> bool {
+        match self.lookup("/executeCommandProvider/commands") {
+            Some(json::Value::Array(commands)) => {
+                commands.iter().any(|advertised| advertised.as_str() == Some(command))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The methods that can be registered dynamically, with the server capability they stand for.
+const DYNAMIC_PROVIDERS: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("textDocument/completion", "completion", "completionProvider"),
+    ("textDocument/hover", "hover", "hoverProvider"),
+    ("textDocument/signatureHelp", "signatureHelp", "signatureHelpProvider"),
+    ("textDocument/codeAction", "codeAction", "codeActionProvider"),
+    ("textDocument/rename", "rename", "renameProvider"),
+    ("textDocument/formatting", "formatting", "documentFormattingProvider"),
+    ("textDocument/rangeFormatting", "rangeFormatting", "documentRangeFormattingProvider"),
+    ("textDocument/semanticTokens", "semanticTokens", "semanticTokensProvider"),
+    ("textDocument/diagnostic", "diagnostic", "diagnosticProvider"),
+];
+
+fn provider_key(method: &str) -> Option<&'static str> {
+    if method == "workspace/executeCommand" {
+        return Some("executeCommandProvider");
+    }
+    DYNAMIC_PROVIDERS.iter()
+        .find(|&&(registered, _, _)| registered == method)
+        .map(|&(_, _, key)| key)
+}
+
+/// Builds the `ClientCapabilities` sent in the initialize request. The switches for features
+/// that need client support, like answering `workspace/applyEdit`, are set by the
+/// `LanguageServer` when that support is enabled.
+#[derive(Clone, Debug)]
+pub struct ClientCapabilitiesBuilder {
+    snippets: bool,
+    markdown: bool,
+    hierarchical_document_symbols: bool,
+    configuration: bool,
+    workspace_folders: bool,
+    apply_edit: bool,
+    work_done_progress: bool,
+    dynamic_registration: bool,
+}
+
+impl ClientCapabilitiesBuilder {
+    pub fn new() -> Self {
+        ClientCapabilitiesBuilder {
+            snippets: true,
+            markdown: true,
+            hierarchical_document_symbols: true,
+            configuration: false,
+            workspace_folders: false,
+            apply_edit: false,
+            work_done_progress: false,
+            dynamic_registration: false,
+        }
+    }
+
+    /// Whether completion items can be snippets, see `snippet`.
+    pub fn snippets(&mut self, enabled: bool) -> &mut Self {
+        self.snippets = enabled;
+        self
+    }
+
+    /// Whether documentation can be markdown rather than plain text.
+    pub fn markdown(&mut self, enabled: bool) -> &mut Self {
+        self.markdown = enabled;
+        self
+    }
+
+    pub fn hierarchical_document_symbols(&mut self, enabled: bool) -> &mut Self {
+        self.hierarchical_document_symbols = enabled;
+        self
+    }
+
+    /// Whether `workspace/configuration` requests are answered.
+    pub fn configuration(&mut self, enabled: bool) -> &mut Self {
+        self.configuration = enabled;
+        self
+    }
+
+    /// Whether `workspace/workspaceFolders` requests are answered.
+    pub fn workspace_folders(&mut self, enabled: bool) -> &mut Self {
+        self.workspace_folders = enabled;
+        self
+    }
+
+    /// Whether `workspace/applyEdit` requests are answered.
+    pub fn apply_edit(&mut self, enabled: bool) -> &mut Self {
+        self.apply_edit = enabled;
+        self
+    }
+
+    /// Whether `window/workDoneProgress/create` requests are answered.
+    pub fn work_done_progress(&mut self, enabled: bool) -> &mut Self {
+        self.work_done_progress = enabled;
+        self
+    }
+
+    /// Whether `client/registerCapability` requests are answered, for the providers that
+    /// `Capabilities::register` knows about.
+    pub fn dynamic_registration(&mut self, enabled: bool) -> &mut Self {
+        self.dynamic_registration = enabled;
+        self
+    }
+
+    pub fn build(&self) -> json::Value {
+        let formats = if self.markdown {
+            vec!["markdown", "plaintext"]
+        } else {
+            vec!["plaintext"]
+        };
+        let dynamic = self.dynamic_registration;
+
+        let mut text_document = ObjectBuilder::new()
+            .insert_object("synchronization", |sync| sync.insert("didSave", true))
+            .insert_object("completion", |completion| {
+                completion.insert("contextSupport", true)
+                    .insert_object("completionItem", |item| {
+                        item.insert("snippetSupport", self.snippets)
+                            .insert("documentationFormat", &formats)
+                            .insert_object("resolveSupport", |resolve| {
+                                resolve.insert_array("properties", |properties| {
+                                    properties.push("documentation").push("detail").push("additionalTextEdits")
+                                })
+                            })
+                    })
+            })
+            .insert_object("hover", |hover| hover.insert("contentFormat", &formats))
+            .insert_object("signatureHelp", |signature_help| {
+                signature_help.insert("contextSupport", true)
+                    .insert_object("signatureInformation", |information| {
+                        information.insert("documentationFormat", &formats)
+                            .insert_object("parameterInformation", |parameter| parameter.insert("labelOffsetSupport", true))
+                            .insert("activeParameterSupport", true)
+                    })
+            })
+            .insert_object("codeAction", |code_action| {
+                code_action.insert_object("codeActionLiteralSupport", |literal| {
+                        literal.insert_object("codeActionKind", |kind| kind.insert("valueSet", kinds::ALL))
+                    })
+                    .insert("isPreferredSupport", true)
+                    .insert("dataSupport", true)
+                    .insert_object("resolveSupport", |resolve| {
+                        resolve.insert_array("properties", |properties| properties.push("edit"))
+                    })
+            })
+            .insert_object("rename", |rename| rename.insert("prepareSupport", true))
+            .insert_object("documentSymbol", |symbol| {
+                symbol.insert("hierarchicalDocumentSymbolSupport", self.hierarchical_document_symbols)
+            })
+            .insert_object("semanticTokens", |tokens| {
+                tokens.insert_object("requests", |requests| {
+                        requests.insert("range", true)
+                            .insert_object("full", |full| full.insert("delta", true))
+                    })
+                    .insert("tokenTypes", TOKEN_TYPES)
+                    .insert("tokenModifiers", TOKEN_MODIFIERS)
+                    .insert_array("formats", |formats| formats.push("relative"))
+            })
+            .insert_object("callHierarchy", |call_hierarchy| call_hierarchy)
+            .insert_object("typeHierarchy", |type_hierarchy| type_hierarchy)
+            .insert_object("foldingRange", |folding| folding.insert("lineFoldingOnly", false))
+            .insert_object("selectionRange", |selection| selection)
+            .insert_object("linkedEditingRange", |linked| linked)
+            .insert_object("colorProvider", |colors| colors)
+            .insert_object("inlayHint", |inlay_hint| {
+                inlay_hint.insert_object("resolveSupport", |resolve| {
+                    resolve.insert_array("properties", |properties| {
+                        properties.push("tooltip").push("textEdits").push("label.location")
+                    })
+                })
+            })
+            .build();
+        if let Some(features) = text_document.as_object_mut() {
+            for &(_, feature, _) in DYNAMIC_PROVIDERS {
+                if !features.contains_key(feature) {
+                    features.insert(feature.to_string(), ObjectBuilder::new().build());
+                }
+                if let Some(feature) = features.get_mut(feature).and_then(|feature| feature.as_object_mut()) {
+                    feature.insert("dynamicRegistration".to_string(), json::Value::Bool(dynamic));
+                }
+            }
+        }
+
+        ObjectBuilder::new()
+            .insert_object("workspace", |workspace| {
+                workspace.insert("configuration", self.configuration)
+                    .insert("workspaceFolders", self.workspace_folders)
+                    .insert("applyEdit", self.apply_edit)
+                    .insert_object("executeCommand", |command| command.insert("dynamicRegistration", dynamic))
+                    .insert_object("inlayHint", |refresh| refresh.insert("refreshSupport", true))
+                    .insert_object("codeLens", |refresh| refresh.insert("refreshSupport", true))
+            })
+            .insert("textDocument", text_document)
+            .insert_object("window", |window| window.insert("workDoneProgress", self.work_done_progress))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json as json;
+
+    #[test]
+    fn switches_are_reflected_in_client_capabilities() {
+        let mut builder = ClientCapabilitiesBuilder::new();
+        builder.markdown(false).apply_edit(true).dynamic_registration(true);
+        let capabilities = builder.build();
+
+        assert_eq!(capabilities.pointer("/workspace/applyEdit"), Some(&json::Value::Bool(true)));
+        assert_eq!(capabilities.pointer("/window/workDoneProgress"), Some(&json::Value::Bool(false)));
+        assert_eq!(capabilities.pointer("/textDocument/hover/contentFormat"),
+                   Some(&json::from_str(r#"["plaintext"]"#).unwrap()));
+        assert_eq!(capabilities.pointer("/textDocument/rangeFormatting/dynamicRegistration"),
+                   Some(&json::Value::Bool(true)));
+        assert_eq!(capabilities.pointer("/textDocument/completion/dynamicRegistration"),
+                   Some(&json::Value::Bool(true)));
+    }
+
+    #[test]
+    fn implemented_features_are_advertised() {
+        let capabilities = ClientCapabilitiesBuilder::new().build();
+        for pointer in &["/textDocument/semanticTokens/tokenTypes",
+                         "/textDocument/semanticTokens/tokenModifiers",
+                         "/textDocument/codeAction/codeActionLiteralSupport/codeActionKind/valueSet",
+                         "/textDocument/callHierarchy",
+                         "/textDocument/typeHierarchy",
+                         "/textDocument/foldingRange",
+                         "/textDocument/selectionRange",
+                         "/textDocument/linkedEditingRange",
+                         "/textDocument/colorProvider",
+                         "/textDocument/inlayHint",
+                         "/textDocument/completion/completionItem/resolveSupport/properties",
+                         "/textDocument/completion/contextSupport"] {
+            assert!(capabilities.pointer(pointer).is_some(), "{} is missing", pointer);
+        }
+        assert_eq!(capabilities.pointer("/textDocument/semanticTokens/tokenTypes/0"),
+                   Some(&json::to_value("namespace")));
+        assert_eq!(capabilities.pointer("/textDocument/codeAction/codeActionLiteralSupport/codeActionKind/valueSet/1"),
+                   Some(&json::to_value("quickfix")));
+    }
+
+    #[test]
+    fn registered_capabilities_can_be_looked_up() {
+        let capabilities = Capabilities::new();
+        capabilities.set(Some(ObjectBuilder::new().build()));
+        assert!(capabilities.register("textDocument/rangeFormatting", json::Value::Null));
+        assert!(!capabilities.register("textDocument/unknown", json::Value::Null));
+        assert!(capabilities.is_enabled("/documentRangeFormattingProvider"));
+        capabilities.unregister("textDocument/rangeFormatting");
+        assert!(!capabilities.is_enabled("/documentRangeFormattingProvider"));
+    }
+
+    #[test]
+    fn only_advertised_commands_are_supported() {
+        let capabilities = Capabilities::new();
+        assert!(!capabilities.supports_command("rust-analyzer.runSingle"));
+
+        capabilities.set(Some(json::from_str(r#"{"executeCommandProvider": {"commands": ["rust-analyzer.runSingle"]}}"#)
+            .unwrap()));
+        assert!(capabilities.supports_command("rust-analyzer.runSingle"));
+        assert!(!capabilities.supports_command("editor.action.triggerSuggest"));
+    }
+
+    #[test]
+    fn registrations_before_initialization_wait_for_it() {
+        let capabilities = Capabilities::new();
+        assert!(capabilities.register("textDocument/rangeFormatting", json::Value::Null));
+        assert!(capabilities.register("textDocument/hover", json::Value::Null));
+        capabilities.unregister("textDocument/hover");
+        assert!(capabilities.get().is_none());
+        assert!(!capabilities.is_enabled("/documentRangeFormattingProvider"));
+
+        capabilities.set(Some(json::from_str(r#"{"completionProvider": {}}"#).unwrap()));
+        assert!(capabilities.is_enabled("/completionProvider"));
+        assert!(capabilities.is_enabled("/documentRangeFormattingProvider"));
+        assert!(!capabilities.is_enabled("/hoverProvider"));
+    }
+}
@@ -1,10 +1,11 @@
 use futures::{Async, AsyncSink, Future, Poll, Sink};
 use futures::stream::{Peekable, SplitSink, Stream};
 use tokio_service::Service;
-use messages::{OutgoingMessage, RequestMessage, Notification, ResponseMessage};
+use messages::{OutgoingMessage, RequestMessage, Notification, ResponseMessage, ServerResponse};
 use error::Error;
 use uuid::Uuid;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 use language_server_io::AsyncChildIo;
 use tokio_core::io::Framed;
@@ -13,27 +14,31 @@ use evented_receiver::EventedReceiver;
 
 type Responses = Rc<RefCell<Peekable<EventedReceiver<ResponseMessage>>>>;
 type ServerInput = Rc<RefCell<SplitSink<Framed<AsyncChildIo, RpcCodec>>>>;
+/// The ids of the requests waiting for a response, with whether they must fail because the
+/// server is being restarted.
+type Pending = Rc<RefCell<HashMap<Uuid, bool>>>;
 
-pub struct NotificationHandle {
-    notification: Option<Notification>,
+/// A message that does not expect a response: a notification, or a response to a request from
+/// the server.
+pub struct MessageHandle {
+    message: Option<OutgoingMessage>,
     server_input: ServerInput,
 }
 
-impl Future for NotificationHandle {
+impl Future for MessageHandle {
     type Item = ();
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        debug!("writing a notification");
+        debug!("writing a message");
         let mut server_input = self.server_input.borrow_mut();
-        if let Some(notification) = self.notification.take() {
-            match server_input.start_send(OutgoingMessage::Notification(notification))? {
+        if let Some(message) = self.message.take() {
+            match server_input.start_send(message)? {
                 AsyncSink::Ready => (),
-                AsyncSink::NotReady(OutgoingMessage::Notification(rejected)) => {
-                    self.notification = Some(rejected);
+                AsyncSink::NotReady(rejected) => {
+                    self.message = Some(rejected);
                     return Ok(Async::NotReady)
                 },
-                AsyncSink::NotReady(_) => unreachable!(),
             }
         }
 
@@ -49,6 +54,13 @@ pub struct RequestHandle {
     request: Option<RequestMessage>,
     responses: Responses,
     server_input: ServerInput,
+    pending: Pending,
+}
+
+impl Drop for RequestHandle {
+    fn drop(&mut self) {
+        self.pending.borrow_mut().remove(&self.id);
+    }
 }
 
 impl Future for RequestHandle {
@@ -57,6 +69,9 @@ impl Future for RequestHandle {
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         debug!("polling for a response, {:?}", self.id);
+        if self.pending.borrow().get(&self.id) == Some(&true) {
+            return Err(Error::ServerRestarted);
+        }
         let mut server_input = self.server_input.borrow_mut();
         if let Some(request) = self.request.take() {
             match server_input.start_send(OutgoingMessage::Request(request))? {
@@ -102,9 +117,11 @@ impl Future for RequestHandle {
     }
 }
 
+#[derive(Clone)]
 pub struct RpcClient {
     server_input: ServerInput,
     responses: Responses,
+    pending: Pending,
 }
 
 impl RpcClient {
@@ -114,12 +131,28 @@ impl RpcClient {
         RpcClient {
             server_input: Rc::new(RefCell::new(server_input)),
             responses: Rc::new(RefCell::new(responses.peekable())),
+            pending: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Makes the requests waiting for a response fail with `Error::ServerRestarted`, rather
+    /// than with the error of the broken connection.
+    pub fn fail_pending_on_restart(&self) {
+        for restarted in self.pending.borrow_mut().values_mut() {
+            *restarted = true;
+        }
+    }
+
+    pub fn notify(&self, notification: Notification) -> MessageHandle {
+        MessageHandle {
+            message: Some(OutgoingMessage::Notification(notification)),
+            server_input: self.server_input.clone(),
         }
     }
 
-    pub fn notify(&self, notification: Notification) -> NotificationHandle {
-        NotificationHandle {
-            notification: Some(notification),
+    pub fn respond(&self, response: ServerResponse) -> MessageHandle {
+        MessageHandle {
+            message: Some(OutgoingMessage::Response(response)),
             server_input: self.server_input.clone(),
         }
     }
@@ -132,11 +165,13 @@ impl Service for RpcClient {
     type Future = RequestHandle;
 
     fn call(&mut self, request: Self::Request) -> Self::Future {
+        self.pending.borrow_mut().insert(request.id, false);
         RequestHandle {
             id: request.id,
             request: Some(request),
             responses: self.responses.clone(),
             server_input: self.server_input.clone(),
+            pending: self.pending.clone(),
         }
     }
 }
@@ -166,13 +201,13 @@ mod test {
     #[test]
     fn rpc_client_can_be_called() {
         let core = Core::new().unwrap();
-        let child = Command::new("/bin/sh")
+        let mut child = Command::new("/bin/sh")
             .arg("hi")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
             .unwrap();
-        let (sink, _) = AsyncChildIo::new(child, &core.handle())
+        let (sink, _) = AsyncChildIo::new(&mut child, &core.handle())
             .unwrap()
             .framed(RpcCodec)
             .split();
@@ -196,7 +231,7 @@ mod test {
         drop(env_logger::init());
 
         let mut core = Core::new().unwrap();
-        let child = Command::new("cat")
+        let mut child = Command::new("cat")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -205,7 +240,7 @@ mod test {
         debug!("started cat");
         let (sender, receiver) = mio::channel::channel();
         let responses = EventedReceiver::new(PollEvented::new(receiver, &core.handle()).unwrap());
-        let (sink, _) = AsyncChildIo::new(child, &core.handle())
+        let (sink, _) = AsyncChildIo::new(&mut child, &core.handle())
             .unwrap()
             .framed(RpcCodec)
             .split();
@@ -0,0 +1,157 @@
+use languageserver_types::{Command, Diagnostic, WorkspaceEdit};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json as json;
+use utils::from_json;
+
+/// Hierarchical code action kinds, as used in `CodeActionContext.only`.
+pub mod kinds {
+    pub const EMPTY: &'static str = "";
+    pub const QUICKFIX: &'static str = "quickfix";
+    pub const REFACTOR: &'static str = "refactor";
+    pub const REFACTOR_EXTRACT: &'static str = "refactor.extract";
+    pub const REFACTOR_INLINE: &'static str = "refactor.inline";
+    pub const REFACTOR_REWRITE: &'static str = "refactor.rewrite";
+    pub const SOURCE: &'static str = "source";
+    pub const SOURCE_ORGANIZE_IMPORTS: &'static str = "source.organizeImports";
+    pub const SOURCE_FIX_ALL: &'static str = "source.fixAll";
+
+    /// The kinds above, as advertised to servers.
+    pub const ALL: &'static [&'static str] = &[EMPTY, QUICKFIX, REFACTOR, REFACTOR_EXTRACT, REFACTOR_INLINE,
+                                               REFACTOR_REWRITE, SOURCE, SOURCE_ORGANIZE_IMPORTS,
+                                               SOURCE_FIX_ALL];
+}
+
+/// Whether `kind` is `filter` or one of its sub-kinds: `refactor` matches `refactor.extract`,
+/// but not `refactoring`.
+pub fn kind_matches(filter: &str, kind: &str) -> bool {
+    filter.is_empty() || kind == filter ||
+    (kind.starts_with(filter) && kind[filter.len()..].starts_with('.'))
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CodeAction {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<Vec<Diagnostic>>,
+    #[serde(rename = "isPreferred", skip_serializing_if = "Option::is_none")]
+    pub is_preferred: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit: Option<WorkspaceEdit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Command>,
+    /// Opaque data used by the server to resolve the action lazily.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<json::Value>,
+}
+
+/// An item in the response to `textDocument/codeAction`.
+#[derive(Clone, Debug)]
+pub enum CodeActionOrCommand {
+    Command(Command),
+    CodeAction(CodeAction),
+}
+
+impl CodeActionOrCommand {
+    pub fn title(&self) -> &str {
+        match *self {
+            CodeActionOrCommand::Command(ref command) => &command.title,
+            CodeActionOrCommand::CodeAction(ref action) => &action.title,
+        }
+    }
+
+    /// Bare commands have no kind.
+    pub fn kind(&self) -> Option<&str> {
+        match *self {
+            CodeActionOrCommand::Command(_) => None,
+            CodeActionOrCommand::CodeAction(ref action) => action.kind.as_ref().map(|k| k.as_str()),
+        }
+    }
+
+    pub fn is_preferred(&self) -> bool {
+        match *self {
+            CodeActionOrCommand::Command(_) => false,
+            CodeActionOrCommand::CodeAction(ref action) => action.is_preferred == Some(true),
+        }
+    }
+}
+
+/// Keep only the actions matching one of `kinds`. An empty list keeps everything.
+pub fn filter_by_kinds(actions: Vec<CodeActionOrCommand>, kinds: &[String]) -> Vec<CodeActionOrCommand> {
+    if kinds.is_empty() {
+        return actions;
+    }
+    actions.into_iter()
+        .filter(|action| {
+            match action.kind() {
+                Some(kind) => kinds.iter().any(|filter| kind_matches(filter, kind)),
+                None => false,
+            }
+        })
+        .collect()
+}
+
+impl Deserialize for CodeActionOrCommand {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        let value = json::Value::deserialize(deserializer)?;
+        // A `Command` has a string `command` field, a `CodeAction` an optional object one.
+        let is_command = value.find("command").map_or(false, |command| command.is_string());
+        if is_command {
+            from_json(value).map(CodeActionOrCommand::Command)
+        } else {
+            from_json(value).map(CodeActionOrCommand::CodeAction)
+        }
+    }
+}
+
+impl Serialize for CodeActionOrCommand {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        match *self {
+            CodeActionOrCommand::Command(ref command) => command.serialize(serializer),
+            CodeActionOrCommand::CodeAction(ref action) => action.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn kind_matches_sub_kinds_only() {
+        assert!(kind_matches(kinds::REFACTOR, kinds::REFACTOR_EXTRACT));
+        assert!(kind_matches(kinds::REFACTOR, kinds::REFACTOR));
+        assert!(!kind_matches(kinds::REFACTOR, "refactoring"));
+        assert!(!kind_matches(kinds::REFACTOR_EXTRACT, kinds::REFACTOR));
+        assert!(kind_matches(kinds::EMPTY, kinds::SOURCE_ORGANIZE_IMPORTS));
+    }
+
+    #[test]
+    fn code_action_results_are_told_apart() {
+        let raw = r#"[
+            {"title": "Run tests", "command": "go.test", "arguments": []},
+            {"title": "Organize imports", "kind": "source.organizeImports",
+             "command": {"title": "noop", "command": "noop"}}
+        ]"#;
+        let actions: Vec<CodeActionOrCommand> = json::from_str(raw).unwrap();
+        match actions[0] {
+            CodeActionOrCommand::Command(ref command) => assert_eq!(command.command, "go.test"),
+            _ => panic!("Was not a Command"),
+        }
+        match actions[1] {
+            CodeActionOrCommand::CodeAction(ref action) => {
+                assert_eq!(action.kind, Some(kinds::SOURCE_ORGANIZE_IMPORTS.to_string()))
+            }
+            _ => panic!("Was not a CodeAction"),
+        }
+
+        let filtered = filter_by_kinds(actions, &[kinds::SOURCE.to_string()]);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].title(), "Organize imports");
+    }
+}
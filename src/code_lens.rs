@@ -0,0 +1,134 @@
+//! Code lenses, cached by document version and resolved lazily: only the lenses in the visible
+//! range of a document are resolved.
+use documents::ranges_overlap;
+use languageserver_types::{CodeLens, Range};
+use std::collections::HashMap;
+
+struct DocumentLenses {
+    version: u64,
+    lenses: Vec<CodeLens>,
+}
+
+/// Code lenses by document, for the version of the document they were requested for. Lenses are
+/// resolved in place as they become visible.
+pub struct CodeLensCache {
+    by_uri: HashMap<String, DocumentLenses>,
+}
+
+impl CodeLensCache {
+    pub fn new() -> Self {
+        CodeLensCache { by_uri: HashMap::new() }
+    }
+
+    pub fn get(&self, uri: &str, version: u64) -> Option<Vec<CodeLens>> {
+        self.by_uri
+            .get(uri)
+            .and_then(|document| if document.version == version {
+                Some(document.lenses.clone())
+            } else {
+                None
+            })
+    }
+
+    pub fn insert(&mut self, uri: String, version: u64, lenses: Vec<CodeLens>) {
+        self.by_uri.insert(uri,
+                           DocumentLenses {
+                               version: version,
+                               lenses: lenses,
+                           });
+    }
+
+    /// Replace a lens by its resolved version, unless the document changed in the meantime.
+    pub fn set_resolved(&mut self, uri: &str, version: u64, index: usize, lens: CodeLens) {
+        if let Some(document) = self.by_uri.get_mut(uri) {
+            if document.version == version && index < document.lenses.len() {
+                document.lenses[index] = lens;
+            }
+        }
+    }
+
+    pub fn invalidate(&mut self, uri: &str) {
+        self.by_uri.remove(uri);
+    }
+
+    pub fn clear(&mut self) {
+        self.by_uri.clear();
+    }
+}
+
+/// The lenses overlapping the visible range, with their index in `lenses`, and whether they still
+/// have to be resolved: lenses without a command are, if the server can resolve them.
+pub fn visible_lenses(lenses: Vec<CodeLens>, visible: &Range, can_resolve: bool) -> Vec<(usize, CodeLens, bool)> {
+    lenses.into_iter()
+        .enumerate()
+        .filter(|&(_, ref lens)| ranges_overlap(&lens.range, visible))
+        .map(|(index, lens)| {
+            let needs_resolving = can_resolve && lens.command.is_none();
+            (index, lens, needs_resolving)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::Position;
+    use serde_json as json;
+
+    fn range(start_line: u64, end_line: u64) -> Range {
+        Range {
+            start: Position { line: start_line, character: 0 },
+            end: Position { line: end_line, character: 0 },
+        }
+    }
+
+    fn lens(line: u64, title: Option<&str>) -> CodeLens {
+        let command = title.map_or("null".to_string(),
+                                   |title| format!(r#"{{"title": "{}", "command": "run"}}"#, title));
+        json::from_str(&format!(r#"{{"range": {{"start": {{"line": {0}, "character": 0}},
+                                               "end": {{"line": {1}, "character": 0}}}},
+                                    "command": {2}}}"#,
+                                line,
+                                line + 1,
+                                command))
+            .unwrap()
+    }
+
+    fn titles(lenses: &[CodeLens]) -> Vec<Option<String>> {
+        lenses.iter().map(|lens| lens.command.as_ref().map(|command| command.title.clone())).collect()
+    }
+
+    #[test]
+    fn lenses_are_cached_by_version_and_resolved_in_place() {
+        let mut cache = CodeLensCache::new();
+        cache.insert("file:///main.rs".to_string(), 1, vec![lens(0, None), lens(10, None)]);
+        assert!(cache.get("file:///main.rs", 2).is_none());
+        assert!(cache.get("file:///other.rs", 1).is_none());
+
+        cache.set_resolved("file:///main.rs", 1, 1, lens(10, Some("Run test")));
+        // Resolutions for another version, or an unknown lens, are ignored.
+        cache.set_resolved("file:///main.rs", 2, 0, lens(0, Some("Stale")));
+        cache.set_resolved("file:///main.rs", 1, 5, lens(0, Some("Unknown")));
+        assert_eq!(titles(&cache.get("file:///main.rs", 1).unwrap()),
+                   vec![None, Some("Run test".to_string())]);
+
+        cache.invalidate("file:///main.rs");
+        assert!(cache.get("file:///main.rs", 1).is_none());
+    }
+
+    #[test]
+    fn only_visible_unresolved_lenses_are_resolved() {
+        let lenses = vec![lens(0, None), lens(10, Some("Run")), lens(20, None), lens(90, None)];
+        let visible: Vec<(usize, bool)> = visible_lenses(lenses.clone(), &range(5, 30), true)
+            .into_iter()
+            .map(|(index, _, needs_resolving)| (index, needs_resolving))
+            .collect();
+        assert_eq!(visible, vec![(1, false), (2, true)]);
+
+        let visible: Vec<(usize, bool)> = visible_lenses(lenses, &range(5, 30), false)
+            .into_iter()
+            .map(|(index, _, needs_resolving)| (index, needs_resolving))
+            .collect();
+        assert_eq!(visible, vec![(1, false), (2, false)]);
+    }
+}
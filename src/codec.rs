@@ -40,6 +40,7 @@ impl Codec for RpcCodec {
     fn encode(&mut self, msg: Self::Out, buf: &mut Vec<u8>) -> Result<(), io::Error> {
         let payload = match msg {
             OutgoingMessage::Request(ref req) => json::to_string(req),
+            OutgoingMessage::Response(ref response) => json::to_string(response),
             OutgoingMessage::Notification(ref notification) => json::to_string(notification),
         }.map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
         buf.write(format!("Content-Length: {}\r\n\r\n", payload.len()).as_bytes())?;
@@ -0,0 +1,100 @@
+//! Document colors, for color decorators and pickers.
+use languageserver_types::{Range, TextDocumentIdentifier, TextEdit};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DocumentColorParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// A color, with components between 0 and 1.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Color {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ColorInformation {
+    pub range: Range,
+    pub color: Color,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ColorPresentationParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+    pub color: Color,
+    pub range: Range,
+}
+
+/// A way of writing a color, like `#ff0000` or `rgb(255, 0, 0)`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ColorPresentation {
+    pub label: String,
+    /// The edit writing the color, replacing `label` at the color range if absent.
+    #[serde(rename = "textEdit", skip_serializing_if = "Option::is_none")]
+    pub text_edit: Option<TextEdit>,
+    #[serde(rename = "additionalTextEdits", skip_serializing_if = "Option::is_none")]
+    pub additional_text_edits: Option<Vec<TextEdit>>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::Position;
+    use serde_json as json;
+
+    #[test]
+    fn colors_are_read_from_the_server() {
+        let colors: Vec<ColorInformation> = json::from_str(r#"[{
+            "range": {"start": {"line": 2, "character": 8}, "end": {"line": 2, "character": 15}},
+            "color": {"red": 1, "green": 0.5, "blue": 0, "alpha": 1}
+        }]"#)
+            .unwrap();
+        assert_eq!(colors[0].range.start, Position { line: 2, character: 8 });
+        assert_eq!(colors[0].color,
+                   Color {
+                       red: 1.0,
+                       green: 0.5,
+                       blue: 0.0,
+                       alpha: 1.0,
+                   });
+
+        let presentations: Vec<ColorPresentation> = json::from_str(r##"[
+            {"label": "#ff8000"},
+            {"label": "rgb(255, 128, 0)", "textEdit": {
+                "range": {"start": {"line": 2, "character": 8}, "end": {"line": 2, "character": 15}},
+                "newText": "rgb(255, 128, 0)"
+            }}
+        ]"##)
+            .unwrap();
+        assert!(presentations[0].text_edit.is_none());
+        assert_eq!(presentations[1].text_edit.as_ref().map(|edit| edit.new_text.as_str()),
+                   Some("rgb(255, 128, 0)"));
+    }
+
+    #[test]
+    fn presentation_params_use_protocol_names() {
+        let range = Range {
+            start: Position { line: 0, character: 0 },
+            end: Position { line: 0, character: 7 },
+        };
+        let params = ColorPresentationParams {
+            text_document: json::from_str(r#"{"uri": "file:///style.css"}"#).unwrap(),
+            color: Color {
+                red: 0.0,
+                green: 0.0,
+                blue: 1.0,
+                alpha: 1.0,
+            },
+            range: range,
+        };
+        let params = json::to_value(&params);
+        assert_eq!(params.pointer("/textDocument/uri"), Some(&json::to_value("file:///style.css")));
+        assert_eq!(params.pointer("/color/blue"), Some(&json::to_value(1.0)));
+        assert_eq!(params.pointer("/range/end/character"), Some(&json::to_value(7)));
+    }
+}
@@ -0,0 +1,72 @@
+//! Client-side commands, for the `Command`s that servers expect the client (and not themselves)
+//! to execute, like `editor.action.triggerSuggest`.
+use error::Result;
+use serde_json as json;
+use std::collections::HashMap;
+
+pub type CommandHandler = Box<Fn(&[json::Value]) -> Result<json::Value>>;
+
+pub struct CommandRegistry {
+    handlers: HashMap<String, CommandHandler>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        CommandRegistry { handlers: HashMap::new() }
+    }
+
+    /// Register a handler for `command`, replacing the previous one if any.
+    pub fn register<F>(&mut self, command: &str, handler: F)
+        where F: Fn(&[json::Value]) -> Result<json::Value> + 'static
+    {
+        self.handlers.insert(command.to_string(), Box::new(handler));
+    }
+
+    /// Returns whether there was a handler registered for `command`.
+    pub fn unregister(&mut self, command: &str) -> bool {
+        self.handlers.remove(command).is_some()
+    }
+
+    pub fn contains(&self, command: &str) -> bool {
+        self.handlers.contains_key(command)
+    }
+
+    pub fn commands(&self) -> Vec<String> {
+        self.handlers.keys().cloned().collect()
+    }
+
+    /// Run the handler for `command`, or return `None` if there is no such client-side command.
+    pub fn run(&self, command: &str, arguments: &[json::Value]) -> Option<Result<json::Value>> {
+        self.handlers.get(command).map(|handler| handler(arguments))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::Error;
+
+    #[test]
+    fn registered_commands_are_run_with_their_arguments() {
+        let mut registry = CommandRegistry::new();
+        registry.register("editor.action.triggerSuggest", |arguments| Ok(json::to_value(arguments.len())));
+        registry.register("editor.action.showReferences",
+                          |_| Err(Error::UnknownCommand("editor.action.showReferences".to_string())));
+        assert!(registry.contains("editor.action.triggerSuggest"));
+
+        let arguments = vec![json::to_value(1), json::to_value("two")];
+        match registry.run("editor.action.triggerSuggest", &arguments) {
+            Some(Ok(result)) => assert_eq!(result, json::to_value(2)),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match registry.run("editor.action.showReferences", &[]) {
+            Some(Err(Error::UnknownCommand(_))) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(registry.run("rust-analyzer.runSingle", &[]).is_none());
+
+        assert!(registry.unregister("editor.action.triggerSuggest"));
+        assert!(!registry.unregister("editor.action.triggerSuggest"));
+        assert_eq!(registry.commands(), vec!["editor.action.showReferences".to_string()]);
+    }
+}
@@ -0,0 +1,379 @@
+//! Completion sessions: the items returned by the server are filtered and sorted on the client
+//! as the user types, and only requested again when the server said the list was incomplete, or
+//! when the user left the word they were requested for.
+use documents::{offset_at, position_at};
+use languageserver_types::{CompletionItem, CompletionList, Position, Range, TextDocumentIdentifier,
+                           TextEdit};
+use serde::{Deserialize, Deserializer};
+use serde_json as json;
+use snippet::{self, SnippetContext};
+use std::cmp::Ordering;
+use utils::from_json;
+
+#[derive(Debug)]
+pub enum CompletionResult {
+    CompletionList(CompletionList),
+    CompletionItems(Vec<CompletionItem>),
+}
+
+impl Deserialize for CompletionResult {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        match json::Value::deserialize(deserializer)? {
+            json::Value::Null => Ok(CompletionResult::CompletionItems(Vec::new())),
+            items @ json::Value::Array(_) => from_json(items).map(CompletionResult::CompletionItems),
+            list => from_json(list).map(CompletionResult::CompletionList),
+        }
+    }
+}
+
+impl CompletionResult {
+    /// The items, and whether the list is incomplete.
+    pub fn into_items(self) -> (Vec<CompletionItem>, bool) {
+        match self {
+            CompletionResult::CompletionList(list) => (list.items, list.is_incomplete),
+            CompletionResult::CompletionItems(items) => (items, false),
+        }
+    }
+}
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The start of the word ending at `position`, and the part of that word before `position`.
+pub fn word_before(text: &str, position: &Position) -> (Position, String) {
+    let end = offset_at(text, position);
+    let start = text[..end]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word_char(c))
+        .last()
+        .map_or(end, |(index, _)| index);
+    (position_at(text, start), text[start..end].to_string())
+}
+
+/// Scores `candidate` against the typed `pattern`, or returns `None` if the pattern characters
+/// do not all appear in order in the candidate. Matching is case-insensitive, and favors
+/// consecutive characters and characters at the start of words.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut pattern_chars = pattern.chars().flat_map(|c| c.to_lowercase()).peekable();
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+
+    for (index, c) in candidate.chars().enumerate() {
+        let matched = match pattern_chars.peek() {
+            Some(&expected) => c.to_lowercase().any(|lower| lower == expected),
+            None => break,
+        };
+        if matched {
+            pattern_chars.next();
+            score += 1;
+            if previous_matched {
+                score += 5;
+            }
+            let word_start = match previous {
+                None => true,
+                Some(previous) => {
+                    !is_word_char(previous) || (previous.is_lowercase() && c.is_uppercase())
+                }
+            };
+            if word_start {
+                score += 10;
+            }
+            if index == 0 {
+                score += 5;
+            }
+        } else {
+            score -= 1;
+        }
+        previous_matched = matched;
+        previous = Some(c);
+    }
+
+    if pattern_chars.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
+pub struct CompletionSession {
+    text_document: TextDocumentIdentifier,
+    word_start: Position,
+    prefix: String,
+    requested_prefix: String,
+    items: Vec<CompletionItem>,
+    is_incomplete: bool,
+    /// Whether the cursor left the word the items were requested for.
+    left_word: bool,
+}
+
+impl CompletionSession {
+    pub fn new(text_document: TextDocumentIdentifier, word_start: Position, prefix: String, result: CompletionResult) -> Self {
+        let (items, is_incomplete) = result.into_items();
+        CompletionSession {
+            text_document: text_document,
+            word_start: word_start,
+            requested_prefix: prefix.clone(),
+            prefix: prefix,
+            items: items,
+            is_incomplete: is_incomplete,
+            left_word: false,
+        }
+    }
+
+    /// The same session, with the items from a new request.
+    pub fn with_result(self, result: CompletionResult) -> Self {
+        let (items, is_incomplete) = result.into_items();
+        CompletionSession {
+            requested_prefix: self.prefix.clone(),
+            items: items,
+            is_incomplete: is_incomplete,
+            left_word: false,
+            ..self
+        }
+    }
+
+    pub fn text_document(&self) -> &TextDocumentIdentifier {
+        &self.text_document
+    }
+
+    /// Where the completed word starts.
+    pub fn word_start(&self) -> &Position {
+        &self.word_start
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The cursor position, after the typed prefix.
+    pub fn cursor(&self) -> Position {
+        Position {
+            line: self.word_start.line,
+            character: self.word_start.character +
+                       self.prefix.chars().map(|c| c.len_utf16() as u64).sum::<u64>(),
+        }
+    }
+
+    /// Update the word typed since the start of the session.
+    pub fn set_prefix(&mut self, prefix: &str) {
+        self.prefix = prefix.to_string();
+    }
+
+    /// Update the word typed since the start of the session from the cursor position, after
+    /// the user typed, deleted or moved. The word starts again if the cursor left it.
+    pub fn move_cursor(&mut self, text: &str, position: &Position) {
+        let (word_start, prefix) = word_before(text, position);
+        if word_start != self.word_start {
+            self.word_start = word_start;
+            self.left_word = true;
+        }
+        self.prefix = prefix;
+    }
+
+    /// Whether the items must be requested again for the current prefix: the server may have
+    /// left out matching items if the list is incomplete, if the prefix no longer extends the
+    /// one the items were requested for, or if the cursor left the word.
+    pub fn needs_requery(&self) -> bool {
+        self.left_word || !self.prefix.starts_with(&self.requested_prefix) ||
+        (self.is_incomplete && self.prefix != self.requested_prefix)
+    }
+
+    /// The items matching the prefix, best matches first, then by `sortText`.
+    pub fn items(&self) -> Vec<&CompletionItem> {
+        let mut scored: Vec<(i64, &CompletionItem)> = self.items
+            .iter()
+            .filter_map(|item| {
+                let filter_text = item.filter_text.as_ref().unwrap_or(&item.label);
+                fuzzy_score(&self.prefix, filter_text).map(|score| (score, item))
+            })
+            .collect();
+        scored.sort_by(|&(a_score, a), &(b_score, b)| {
+            match b_score.cmp(&a_score) {
+                Ordering::Equal => sort_text(a).cmp(sort_text(b)),
+                ordering => ordering,
+            }
+        });
+        scored.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// The edits inserting an item: the main edit replaces the typed prefix, and the additional
+    /// edits (e.g. imports) follow.
+    pub fn edits_for(&self, item: &CompletionItem) -> Vec<TextEdit> {
+        let context = SnippetContext::for_document(&self.text_document.uri.to_string());
+        self.insertion_for(item, &context).edits
+    }
+
+    /// Like `edits_for`, with the tabstops of the inserted text when the item is a snippet. The
+    /// tabstop ranges do not account for the additional edits.
+    pub fn insertion_for(&self, item: &CompletionItem, context: &SnippetContext) -> Insertion {
+        let cursor = self.cursor();
+        let mut main_edit = match item.text_edit {
+            // The edit was computed when the item was requested: extend it over what was
+            // typed since.
+            Some(ref edit) => {
+                TextEdit {
+                    range: Range {
+                        start: edit.range.start.clone(),
+                        end: cursor,
+                    },
+                    new_text: edit.new_text.clone(),
+                }
+            }
+            None => {
+                TextEdit {
+                    range: Range {
+                        start: self.word_start.clone(),
+                        end: cursor,
+                    },
+                    new_text: item.insert_text.clone().unwrap_or_else(|| item.label.clone()),
+                }
+            }
+        };
+
+        let mut tabstops = Vec::new();
+        if snippet::is_snippet(item) {
+            let expansion = snippet::expand(&snippet::parse(&main_edit.new_text), context);
+            tabstops = expansion.tabstop_ranges(&main_edit.range.start);
+            main_edit.new_text = expansion.text;
+        }
+
+        let mut edits = vec![main_edit];
+        if let Some(ref additional_edits) = item.additional_text_edits {
+            edits.extend(additional_edits.iter().cloned());
+        }
+        Insertion {
+            edits: edits,
+            tabstops: tabstops,
+        }
+    }
+}
+
+/// What accepting a completion item does to the document.
+#[derive(Debug)]
+pub struct Insertion {
+    pub edits: Vec<TextEdit>,
+    /// The tabstop indices with their ranges, in the order the cursor goes through them. Empty
+    /// unless the item is a snippet.
+    pub tabstops: Vec<(u32, Vec<Range>)>,
+}
+
+fn sort_text(item: &CompletionItem) -> &str {
+    item.sort_text.as_ref().unwrap_or(&item.label)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::{Position, TextDocumentIdentifier};
+    use serde_json as json;
+
+    #[test]
+    fn fuzzy_score_favors_word_starts() {
+        assert_eq!(fuzzy_score("xyz", "format"), None);
+        assert!(fuzzy_score("", "anything").is_some());
+        assert!(fuzzy_score("fmt", "format").is_some());
+        assert!(fuzzy_score("gs", "getString") > fuzzy_score("gs", "glass"));
+        assert!(fuzzy_score("get", "getString") > fuzzy_score("get", "forget"));
+    }
+
+    #[test]
+    fn word_before_finds_the_typed_prefix() {
+        let (start, prefix) = word_before("let x = foo.ba", &Position { line: 0, character: 14 });
+        assert_eq!(start, Position { line: 0, character: 12 });
+        assert_eq!(prefix, "ba");
+    }
+
+    #[test]
+    fn completion_session_filters_and_builds_edits() {
+        let result: CompletionResult = json::from_str(r#"{
+            "isIncomplete": true,
+            "items": [
+                {"label": "bar", "sortText": "2"},
+                {"label": "baz", "sortText": "1", "insertText": "baz()",
+                 "additionalTextEdits": [{"newText": "use baz;\n",
+                     "range": {"start": {"line": 0, "character": 0},
+                               "end": {"line": 0, "character": 0}}}]},
+                {"label": "quux"}
+            ]
+        }"#)
+            .unwrap();
+        let text_document: TextDocumentIdentifier =
+            json::from_str(r#"{"uri": "file:///main.rs"}"#).unwrap();
+        let mut session = CompletionSession::new(text_document,
+                                                 Position { line: 3, character: 4 },
+                                                 "b".to_string(),
+                                                 result);
+
+        let labels: Vec<&str> = session.items().iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["baz", "bar"]);
+        assert!(!session.needs_requery());
+
+        session.set_prefix("baz");
+        assert!(session.needs_requery());
+        let items = session.items();
+        assert_eq!(items.len(), 1);
+
+        let edits = session.edits_for(items[0]);
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[0].new_text, "baz()");
+        assert_eq!(edits[0].range.end, Position { line: 3, character: 7 });
+    }
+
+    #[test]
+    fn snippet_items_are_expanded() {
+        let result: CompletionResult = json::from_str(r#"[
+            {"label": "main", "insertTextFormat": 2, "insertText": "fn ${1:$TM_FILENAME_BASE}() {$0}"}
+        ]"#)
+            .unwrap();
+        let text_document: TextDocumentIdentifier =
+            json::from_str(r#"{"uri": "file:///src/main.rs"}"#).unwrap();
+        let session = CompletionSession::new(text_document,
+                                             Position { line: 1, character: 0 },
+                                             "ma".to_string(),
+                                             result);
+
+        let items = session.items();
+        let insertion = session.insertion_for(items[0], &SnippetContext::for_document("file:///src/main.rs"));
+        assert_eq!(insertion.edits[0].new_text, "fn main() {}");
+        assert_eq!(insertion.tabstops[0].1[0],
+                   Range {
+                       start: Position { line: 1, character: 3 },
+                       end: Position { line: 1, character: 7 },
+                   });
+        assert_eq!(insertion.tabstops[1].1[0].start, Position { line: 1, character: 11 });
+    }
+
+    #[test]
+    fn leaving_the_requested_word_needs_a_requery() {
+        let text = "let x = ba";
+        let complete: CompletionResult = json::from_str(r#"[{"label": "bar"}, {"label": "baz"}]"#).unwrap();
+        let text_document: TextDocumentIdentifier = json::from_str(r#"{"uri": "file:///main.rs"}"#).unwrap();
+        let mut session = CompletionSession::new(text_document,
+                                                 Position { line: 0, character: 8 },
+                                                 "ba".to_string(),
+                                                 complete);
+
+        session.set_prefix("bar");
+        assert!(!session.needs_requery());
+        // Deleting below the requested prefix.
+        session.move_cursor(text, &Position { line: 0, character: 9 });
+        assert_eq!(session.prefix(), "b");
+        assert!(session.needs_requery());
+
+        session.move_cursor(text, &Position { line: 0, character: 10 });
+        assert!(!session.needs_requery());
+        // Moving before the start of the word.
+        session.move_cursor(text, &Position { line: 0, character: 7 });
+        assert_eq!(session.word_start(), &Position { line: 0, character: 7 });
+        assert!(session.needs_requery());
+
+        let session = session.with_result(json::from_str("[]").unwrap());
+        assert!(!session.needs_requery());
+        assert_eq!(session.cursor(), Position { line: 0, character: 7 });
+    }
+}
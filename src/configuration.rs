@@ -0,0 +1,212 @@
+//! The settings tree used to answer `workspace/configuration` requests. Settings can be
+//! overridden for a scope, like a workspace folder, and are then merged into the global ones for
+//! the documents under it.
+use error::{Error, Result};
+use serde_json as json;
+use serde_json::builder::ObjectBuilder;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use toml;
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    global: json::Value,
+    /// Overrides by scope URI, applied to the scope and everything under it.
+    scoped: Vec<(String, json::Value)>,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Settings::from_value(ObjectBuilder::new().build())
+    }
+
+    pub fn from_value(global: json::Value) -> Self {
+        Settings {
+            global: global,
+            scoped: Vec::new(),
+        }
+    }
+
+    /// Loads global settings from a JSON file, or a TOML file if its extension is `toml`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        if path.extension().map_or(false, |extension| extension == "toml") {
+            let value = text.parse::<toml::Value>()
+                .map_err(|err| Error::InvalidSettings(format!("{}: {:?}", path.display(), err)))?;
+            Ok(Settings::from_value(toml_to_json(value)))
+        } else {
+            Ok(Settings::from_value(json::from_str(&text)?))
+        }
+    }
+
+    pub fn global(&self) -> &json::Value {
+        &self.global
+    }
+
+    /// Sets the value at a dotted section, like `gopls.staticcheck`, creating the intermediate
+    /// objects.
+    pub fn set(&mut self, section: &str, value: json::Value) {
+        set_section(&mut self.global, section, value)
+    }
+
+    /// Sets a value for a scope only, overriding the global one.
+    pub fn set_scoped(&mut self, scope_uri: &str, section: &str, value: json::Value) {
+        let index = match self.scoped.iter().position(|&(ref uri, _)| uri == scope_uri) {
+            Some(index) => index,
+            None => {
+                self.scoped.push((scope_uri.to_string(), ObjectBuilder::new().build()));
+                // Broader scopes first, so that narrower ones override them when merging.
+                self.scoped.sort_by(|a, b| a.0.len().cmp(&b.0.len()));
+                self.scoped.iter().position(|&(ref uri, _)| uri == scope_uri).unwrap()
+            }
+        };
+        set_section(&mut self.scoped[index].1, section, value);
+    }
+
+    /// The value of a section, or of the whole tree if `section` is `None`, as seen from a
+    /// scope. Unknown sections are `null`.
+    pub fn get(&self, section: Option<&str>, scope_uri: Option<&str>) -> json::Value {
+        let mut settings = self.global.clone();
+        if let Some(scope_uri) = scope_uri {
+            for &(ref uri, ref overrides) in &self.scoped {
+                if is_in_scope(scope_uri, uri) {
+                    merge(&mut settings, overrides);
+                }
+            }
+        }
+        match section {
+            Some(section) => {
+                section.split('.')
+                    .fold(Some(&settings), |value, key| value.and_then(|value| value.find(key)))
+                    .cloned()
+                    .unwrap_or(json::Value::Null)
+            }
+            None => settings,
+        }
+    }
+
+    /// The answer to the params of a `workspace/configuration` request: one value per item.
+    pub fn answer(&self, params: &json::Value) -> json::Value {
+        let items = match params.find("items") {
+            Some(&json::Value::Array(ref items)) => items.clone(),
+            _ => Vec::new(),
+        };
+        json::Value::Array(items.iter()
+            .map(|item| {
+                self.get(item.find("section").and_then(|section| section.as_str()),
+                         item.find("scopeUri").and_then(|uri| uri.as_str()))
+            })
+            .collect())
+    }
+}
+
+fn is_in_scope(uri: &str, scope: &str) -> bool {
+    uri.starts_with(scope) &&
+    (uri.len() == scope.len() || scope.ends_with('/') || uri[scope.len()..].starts_with('/'))
+}
+
+fn set_section(tree: &mut json::Value, section: &str, value: json::Value) {
+    let mut keys: Vec<&str> = section.split('.').collect();
+    let last = keys.pop().unwrap_or("");
+    let mut node = tree;
+    for key in keys {
+        let current = node;
+        if current.find(key).map_or(true, |child| !child.is_object()) {
+            insert(current, key, ObjectBuilder::new().build());
+        }
+        node = current.as_object_mut().and_then(|object| object.get_mut(key)).unwrap();
+    }
+    insert(node, last, value);
+}
+
+fn insert(object: &mut json::Value, key: &str, value: json::Value) {
+    if !object.is_object() {
+        *object = ObjectBuilder::new().build();
+    }
+    if let Some(object) = object.as_object_mut() {
+        object.insert(key.to_string(), value);
+    }
+}
+
+/// Merges `overrides` into `base`, recursively for objects.
+fn merge(base: &mut json::Value, overrides: &json::Value) {
+    match (base.as_object_mut(), overrides.as_object()) {
+        (Some(base), Some(overrides)) => {
+            for (key, value) in overrides {
+                let merged = match base.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        merge(existing, value);
+                        true
+                    }
+                    _ => false,
+                };
+                if !merged {
+                    base.insert(key.clone(), value.clone());
+                }
+            }
+            return;
+        }
+        _ => (),
+    }
+    *base = overrides.clone();
+}
+
+pub fn toml_to_json(value: toml::Value) -> json::Value {
+    match value {
+        toml::Value::String(string) => json::Value::String(string),
+        toml::Value::Integer(integer) => json::to_value(&integer),
+        toml::Value::Float(float) => json::to_value(&float),
+        toml::Value::Boolean(boolean) => json::Value::Bool(boolean),
+        toml::Value::Datetime(datetime) => json::Value::String(datetime.to_string()),
+        toml::Value::Array(values) => json::Value::Array(values.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => {
+            let object: BTreeMap<String, json::Value> = table.into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect();
+            json::to_value(&object)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json as json;
+
+    #[test]
+    fn sections_are_answered_per_scope() {
+        let mut settings = Settings::new();
+        settings.set("gopls.staticcheck", json::Value::Bool(false));
+        settings.set("gopls.buildFlags", json::from_str(r#"["-tags=integration"]"#).unwrap());
+        settings.set_scoped("file:///work/project", "gopls.staticcheck", json::Value::Bool(true));
+
+        let params = json::from_str(r#"{"items": [
+            {"section": "gopls.staticcheck"},
+            {"section": "gopls.staticcheck", "scopeUri": "file:///work/project/main.go"},
+            {"section": "gopls.staticcheck", "scopeUri": "file:///work/project2/main.go"},
+            {"section": "gopls", "scopeUri": "file:///work/project"},
+            {"section": "pyright"}
+        ]}"#)
+            .unwrap();
+        let expected: json::Value = json::from_str(r#"[
+            false, true, false,
+            {"staticcheck": true, "buildFlags": ["-tags=integration"]},
+            null
+        ]"#)
+            .unwrap();
+        assert_eq!(settings.answer(&params), expected);
+    }
+
+    #[test]
+    fn toml_tables_become_objects() {
+        let value = "[python.analysis]\ntypeCheckingMode = \"strict\"\nextraPaths = [\"lib\"]\n"
+            .parse::<toml::Value>()
+            .unwrap();
+        let expected: json::Value =
+            json::from_str(r#"{"python": {"analysis": {"typeCheckingMode": "strict", "extraPaths": ["lib"]}}}"#).unwrap();
+        assert_eq!(toml_to_json(value), expected);
+    }
+}
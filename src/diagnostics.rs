@@ -0,0 +1,31 @@
+//! Pulled diagnostics, requested for a document rather than published by the server.
+use languageserver_types::{Diagnostic, TextDocumentIdentifier};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DocumentDiagnosticParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+    /// The identifier the server registered the diagnostics under, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    /// The `resultId` of the last report, to get an `unchanged` report if nothing changed.
+    #[serde(rename = "previousResultId", skip_serializing_if = "Option::is_none")]
+    pub previous_result_id: Option<String>,
+}
+
+/// A `full` report with all the diagnostics of a document, or an `unchanged` one without items.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DocumentDiagnosticReport {
+    pub kind: String,
+    #[serde(rename = "resultId")]
+    pub result_id: Option<String>,
+    #[serde(default)]
+    pub items: Vec<Diagnostic>,
+}
+
+impl DocumentDiagnosticReport {
+    /// Whether the diagnostics are the same as in the report with `previous_result_id`.
+    pub fn is_unchanged(&self) -> bool {
+        self.kind == "unchanged"
+    }
+}
@@ -0,0 +1,247 @@
+//! Line-based unified diffs, to preview edits before they are applied.
+use std::cmp;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// The line operations turning `old` into `new`, with Myers' algorithm. The common prefix and
+/// suffix are skipped first, and the rest is split around the middle of the edit path, so that
+/// memory stays linear in the number of lines.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let mut lines = Vec::with_capacity(cmp::max(old.len(), new.len()));
+    diff_into(old, new, &mut lines);
+    lines
+}
+
+fn diff_into<'a>(old: &[&'a str], new: &[&'a str], lines: &mut Vec<Line<'a>>) {
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    lines.extend(old[..prefix].iter().map(|line| Line::Same(*line)));
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old.iter().rev().zip(new.iter().rev()).take_while(|&(a, b)| a == b).count();
+    let (middle_old, middle_new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    if middle_old.is_empty() {
+        lines.extend(middle_new.iter().map(|line| Line::Added(*line)));
+    } else if middle_new.is_empty() {
+        lines.extend(middle_old.iter().map(|line| Line::Removed(*line)));
+    } else {
+        match middle_split(middle_old, middle_new) {
+            Some((x, y)) => {
+                diff_into(&middle_old[..x], &middle_new[..y], lines);
+                diff_into(&middle_old[x..], &middle_new[y..], lines);
+            }
+            None => {
+                lines.extend(middle_old.iter().map(|line| Line::Removed(*line)));
+                lines.extend(middle_new.iter().map(|line| Line::Added(*line)));
+            }
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|line| Line::Same(*line)));
+}
+
+/// A point in the middle of a shortest edit path between `old` and `new`, found by searching
+/// from both ends until the paths overlap. `None` if the texts have no line in common.
+fn middle_split(old: &[&str], new: &[&str]) -> Option<(usize, usize)> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let length = 2 * max_d + 2;
+    // forward[offset + k] is the furthest x reached on diagonal k = x - y from the start, and
+    // backward[offset + k] the same from the end.
+    let mut forward = vec![-1isize; length as usize];
+    let mut backward = vec![-1isize; length as usize];
+    forward[(offset + 1) as usize] = 0;
+    backward[(offset + 1) as usize] = 0;
+    let delta = n - m;
+    // With an odd delta, the paths overlap during a forward step; otherwise a backward one.
+    let front = delta % 2 != 0;
+    let (mut forward_start, mut forward_end) = (0, 0);
+    let (mut backward_start, mut backward_end) = (0, 0);
+
+    for d in 0..max_d {
+        let mut k = -d + forward_start;
+        while k <= d - forward_end {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && forward[index - 1] < forward[index + 1]) {
+                forward[index + 1]
+            } else {
+                forward[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index] = x;
+            if x > n {
+                forward_end += 2;
+            } else if y > m {
+                forward_start += 2;
+            } else if front {
+                let backward_index = offset + delta - k;
+                if backward_index >= 0 && backward_index < length && backward[backward_index as usize] != -1 &&
+                   x >= n - backward[backward_index as usize] {
+                    return Some((x as usize, y as usize));
+                }
+            }
+            k += 2;
+        }
+
+        let mut k = -d + backward_start;
+        while k <= d - backward_end {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && backward[index - 1] < backward[index + 1]) {
+                backward[index + 1]
+            } else {
+                backward[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index] = x;
+            if x > n {
+                backward_end += 2;
+            } else if y > m {
+                backward_start += 2;
+            } else if !front {
+                let forward_index = offset + delta - k;
+                if forward_index >= 0 && forward_index < length && forward[forward_index as usize] != -1 {
+                    let forward_x = forward[forward_index as usize];
+                    let forward_y = offset + forward_x - forward_index;
+                    if forward_x >= n - x {
+                        return Some((forward_x as usize, forward_y as usize));
+                    }
+                }
+            }
+            k += 2;
+        }
+    }
+    None
+}
+
+/// A unified diff between two texts, with `context` unchanged lines around each change. Empty
+/// if the texts have the same lines.
+pub fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str, context: usize) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = lines.iter()
+        .enumerate()
+        .filter(|&(_, line)| match *line {
+            Line::Same(_) => false,
+            _ => true,
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group the changes that are close enough for their context to touch.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &index in &changes {
+        let start = index.saturating_sub(context);
+        let end = cmp::min(index + context + 1, lines.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => {
+                hunk.1 = end;
+                continue;
+            }
+            _ => (),
+        }
+        hunks.push((start, end));
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        let counts = |range: &[Line]| {
+            range.iter().fold((0, 0), |(old, new), line| {
+                match *line {
+                    Line::Same(_) => (old + 1, new + 1),
+                    Line::Removed(_) => (old + 1, new),
+                    Line::Added(_) => (old, new + 1),
+                }
+            })
+        };
+        let (old_before, new_before) = counts(&lines[..start]);
+        let (old_count, new_count) = counts(&lines[start..end]);
+        let first_line = |before: usize, count: usize| if count == 0 { before } else { before + 1 };
+
+        diff.push_str(&format!("@@ -{},{} +{},{} @@\n",
+                               first_line(old_before, old_count),
+                               old_count,
+                               first_line(new_before, new_count),
+                               new_count));
+        for line in &lines[start..end] {
+            let (prefix, text) = match *line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            diff.push(prefix);
+            diff.push_str(text);
+            diff.push('\n');
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identical_texts_have_no_diff() {
+        assert_eq!(unified_diff("a", "b", "x\ny\n", "x\ny\n", 3), "");
+    }
+
+    #[test]
+    fn changes_are_grouped_in_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\ntwo\n3\n4\n5\n6\n7\n8\n9\nten\n";
+        assert_eq!(unified_diff("old", "new", old, new, 1),
+                   "--- old\n+++ new\n\
+                    @@ -1,3 +1,3 @@\n 1\n-2\n+two\n 3\n\
+                    @@ -9,1 +9,2 @@\n 9\n+ten\n");
+        assert_eq!(unified_diff("old", "new", "", "a\n", 3), "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+a\n");
+    }
+
+    #[test]
+    fn diffs_are_minimal() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let lines = diff_lines(&old, &new);
+        let changes = lines.iter().filter(|line| match **line {
+            Line::Same(_) => false,
+            _ => true,
+        });
+        // The shortest edit script of Myers' paper example has 5 operations.
+        assert_eq!(changes.count(), 5);
+
+        let applied: Vec<&str> = lines.iter()
+            .filter_map(|line| match *line {
+                Line::Removed(_) => None,
+                Line::Same(text) | Line::Added(text) => Some(text),
+            })
+            .collect();
+        assert_eq!(applied, new);
+    }
+
+    #[test]
+    fn large_files_are_diffed_without_a_quadratic_table() {
+        let old: Vec<String> = (0..20000).map(|i| format!("line {}", i)).collect();
+        let mut new = old.clone();
+        new[10000] = "changed".to_string();
+        let old = old.join("\n");
+        let new = new.join("\n");
+        assert_eq!(unified_diff("old", "new", &old, &new, 0),
+                   "--- old\n+++ new\n@@ -10001,1 +10001,1 @@\n-line 10000\n+changed\n");
+    }
+}
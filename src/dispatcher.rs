@@ -6,7 +6,13 @@ use error::Error;
 
 fn handle_object(json_object: Map<String, Value>) -> Result<IncomingMessage, Error> {
     let has_id = json_object.get("id").is_some();
-    if has_id {
+    let has_method = json_object.get("method").is_some();
+    if has_id && has_method {
+        let deserialized_request =
+            from_value::<messages::ServerRequest>(Value::Object(json_object))?;
+        debug!("is a request");
+        Ok(IncomingMessage::Request(deserialized_request))
+    } else if has_id {
         let deserialized_response =
             from_value::<messages::ResponseMessage>(Value::Object(json_object))?;
         debug!("is a response");
@@ -70,4 +76,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn handle_raw_message_recognizes_server_requests() {
+        let message = builder::ObjectBuilder::new()
+            .insert("jsonrpc", "2.0")
+            .insert("id", 3)
+            .insert("method", "workspace/inlayHint/refresh")
+            .build();
+
+        match handle_raw_message(message).expect("Could not parse message") {
+            IncomingMessage::Request(request) => {
+                assert_eq!(request.id, json::to_value(3));
+                assert_eq!(request.method, "workspace/inlayHint/refresh");
+                assert_eq!(request.params, None);
+            }
+            _ => panic!("Was not a Request"),
+        }
+    }
+
 }
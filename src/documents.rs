@@ -0,0 +1,252 @@
+//! Tracking of the documents opened on the server, so that edits coming from the server can be
+//! applied to them and the server kept in sync.
+use error::{Error, Result};
+use languageserver_types::{Position, Range, TextDocumentContentChangeEvent, TextEdit,
+                           WorkspaceEdit};
+use serde_json as json;
+use serde_json::builder::ObjectBuilder;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenDocument {
+    pub uri: String,
+    pub language_id: String,
+    pub version: u64,
+    pub text: String,
+}
+
+pub struct Documents {
+    open: HashMap<String, OpenDocument>,
+}
+
+impl Documents {
+    pub fn new() -> Self {
+        Documents { open: HashMap::new() }
+    }
+
+    pub fn open(&mut self, document: OpenDocument) {
+        self.open.insert(document.uri.clone(), document);
+    }
+
+    pub fn close(&mut self, uri: &str) -> Option<OpenDocument> {
+        self.open.remove(uri)
+    }
+
+    pub fn get(&self, uri: &str) -> Option<&OpenDocument> {
+        self.open.get(uri)
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a OpenDocument> + 'a {
+        self.open.values()
+    }
+
+    /// Apply the changes from a `didChange` notification.
+    pub fn change(&mut self, uri: &str, version: u64, changes: &[TextDocumentContentChangeEvent]) {
+        if let Some(document) = self.open.get_mut(uri) {
+            for change in changes {
+                document.text = match change.range {
+                    Some(ref range) => {
+                        let start = offset_at(&document.text, &range.start);
+                        let end = offset_at(&document.text, &range.end);
+                        splice(&document.text, start, end, &change.text)
+                    }
+                    None => change.text.clone(),
+                };
+            }
+            document.version = version;
+        }
+    }
+
+    /// Apply edits to an open document and bump its version. Returns the new version and text,
+    /// or `None` if the document is not open.
+    pub fn apply_edits(&mut self, uri: &str, edits: &[TextEdit]) -> Option<(u64, String)> {
+        self.open.get_mut(uri).map(|document| {
+            document.text = apply_text_edits(&document.text, edits);
+            document.version += 1;
+            (document.version, document.text.clone())
+        })
+    }
+}
+
+/// The byte offset of an LSP position, whose `character` counts UTF-16 code units. Positions
+/// past the end of a line or of the text are clamped.
+pub fn offset_at(text: &str, position: &Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(index) => line_start += index + 1,
+            None => return text.len(),
+        }
+    }
+    let line_end = text[line_start..].find('\n').map_or(text.len(), |index| line_start + index);
+    let mut units = 0;
+    for (index, c) in text[line_start..line_end].char_indices() {
+        if units >= position.character {
+            return line_start + index;
+        }
+        units += c.len_utf16() as u64;
+    }
+    line_end
+}
+
+/// The LSP position of a byte offset.
+pub fn position_at(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    Position {
+        line: before.matches('\n').count() as u64,
+        character: before[line_start..].chars().map(|c| c.len_utf16() as u64).sum(),
+    }
+}
+
+pub fn compare_positions(a: &Position, b: &Position) -> Ordering {
+    (a.line, a.character).cmp(&(b.line, b.character))
+}
+
+/// Whether `position` is within `range`, end included so that a cursor right after a word is
+/// still considered on it.
+pub fn range_contains(range: &Range, position: &Position) -> bool {
+    compare_positions(&range.start, position) != Ordering::Greater &&
+    compare_positions(position, &range.end) != Ordering::Greater
+}
+
+pub fn range_contains_range(outer: &Range, inner: &Range) -> bool {
+    range_contains(outer, &inner.start) && range_contains(outer, &inner.end)
+}
+
+pub fn ranges_overlap(a: &Range, b: &Range) -> bool {
+    compare_positions(&a.start, &b.end) != Ordering::Greater &&
+    compare_positions(&b.start, &a.end) != Ordering::Greater
+}
+
+fn splice(text: &str, start: usize, end: usize, replacement: &str) -> String {
+    let mut result = String::with_capacity(text.len() + replacement.len());
+    result.push_str(&text[..start]);
+    result.push_str(replacement);
+    result.push_str(&text[end..]);
+    result
+}
+
+/// Apply non-overlapping text edits, all expressed relative to the original text. Insertions at
+/// the same position end up in the order of the edits, and before a replacement starting there.
+pub fn apply_text_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut offsets: Vec<(usize, usize, usize)> = edits.iter()
+        .enumerate()
+        .map(|(index, edit)| {
+            (offset_at(text, &edit.range.start), offset_at(text, &edit.range.end), index)
+        })
+        .collect();
+    offsets.sort_by(|a, b| b.cmp(a));
+
+    let mut result = text.to_string();
+    for (start, end, index) in offsets {
+        result = splice(&result, start, end, &edits[index].new_text);
+    }
+    result
+}
+
+/// The text edits of a `WorkspaceEdit`, by document URI. `documentChanges` take precedence over
+/// `changes` when both are present. Resource operations are not supported: an edit containing
+/// some is rejected, rather than partially applied.
+pub fn workspace_edit_changes(edit: &WorkspaceEdit) -> Result<Vec<(String, Vec<TextEdit>)>> {
+    let value = json::to_value(edit);
+    let mut changes = Vec::new();
+
+    if let Some(&json::Value::Array(ref document_changes)) = value.find("documentChanges") {
+        for change in document_changes {
+            match (change.pointer("/textDocument/uri").and_then(|uri| uri.as_str()),
+                   change.find("edits")) {
+                (Some(uri), Some(edits)) => {
+                    changes.push((uri.to_string(), json::from_value(edits.clone())?))
+                }
+                _ => return Err(Error::Unsupported("resource operations")),
+            }
+        }
+    } else if let Some(&json::Value::Object(ref by_uri)) = value.find("changes") {
+        for (uri, edits) in by_uri {
+            changes.push((uri.clone(), json::from_value(edits.clone())?));
+        }
+    }
+
+    Ok(changes)
+}
+
+fn file_path(uri: &str) -> Result<PathBuf> {
+    Url::parse(uri)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| Error::InvalidUri(uri.to_string()))
+}
+
+/// The content of a file that is not open.
+pub fn read_from_disk(uri: &str) -> Result<String> {
+    let mut text = String::new();
+    File::open(&file_path(uri)?)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Replace the content of a file that is not open, directly on disk.
+pub fn write_to_disk(uri: &str, text: &str) -> Result<()> {
+    File::create(&file_path(uri)?)?.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+/// `didChange` params replacing the whole content of a document.
+pub fn full_text_change(uri: &str, version: u64, text: &str) -> json::Value {
+    ObjectBuilder::new()
+        .insert_object("textDocument", |builder| {
+            builder.insert("uri", uri).insert("version", version)
+        })
+        .insert_array("contentChanges", |builder| {
+            builder.push_object(|builder| builder.insert("text", text))
+        })
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::{Position, Range, TextEdit};
+
+    fn edit(start: (u64, u64), end: (u64, u64), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range {
+                start: Position { line: start.0, character: start.1 },
+                end: Position { line: end.0, character: end.1 },
+            },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn offset_at_counts_utf16_code_units() {
+        let text = "ab\n𝄞cd\n";
+        assert_eq!(offset_at(text, &Position { line: 1, character: 2 }), 7);
+        assert_eq!(offset_at(text, &Position { line: 1, character: 99 }), 9);
+        assert_eq!(offset_at(text, &Position { line: 7, character: 0 }), text.len());
+        assert_eq!(position_at(text, 7), Position { line: 1, character: 2 });
+    }
+
+    #[test]
+    fn apply_text_edits_uses_original_positions() {
+        let text = "fn main() {\n    foo();\n}\n";
+        let edits = vec![edit((1, 4), (1, 7), "bar"),
+                         edit((0, 3), (0, 7), "start"),
+                         edit((2, 1), (2, 1), "\n// a"),
+                         edit((2, 1), (2, 1), "b")];
+        assert_eq!(apply_text_edits(text, &edits),
+                   "fn start() {\n    bar();\n}\n// ab\n");
+    }
+
+    #[test]
+    fn insertions_go_before_a_replacement_at_the_same_position() {
+        let text = "let x = 1;\n";
+        let edits = vec![edit((0, 4), (0, 5), "y"), edit((0, 4), (0, 4), "mut ")];
+        assert_eq!(apply_text_edits(text, &edits), "let mut y = 1;\n");
+    }
+}
@@ -26,6 +26,24 @@ pub enum Error {
     Deserialization(serde_json::Error),
     Io(io::Error),
     OOL,
+    /// The command is neither registered on the client nor advertised by the server.
+    UnknownCommand(String),
+    /// A URI that does not point to a local file.
+    InvalidUri(String),
+    /// The server does not advertise the capability needed for a request.
+    Unsupported(&'static str),
+    /// A settings file that could not be parsed.
+    InvalidSettings(String),
+    /// A file whose language is not handled by the server.
+    UnknownLanguage(String),
+    /// A language server binary that is not in the `PATH`, with the `PATH` searched.
+    ServerNotFound { name: String, path: String },
+    /// The server refused to initialize, with the message it gave.
+    InitializeFailed(String),
+    /// A document that is not open in the workspace.
+    DocumentNotOpen(String),
+    /// The server exited before answering, and is restarted by its `Supervisor`.
+    ServerRestarted,
 }
 
 impl From<()> for Error {
@@ -49,8 +67,15 @@ impl From<sync::mpsc::TryRecvError> for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> StdResult<(), fmt::Error> {
-        let out = format!("{:?}", self);
-        formatter.write_str(&out)
+        match *self {
+            Error::ServerNotFound { ref name, ref path } => {
+                write!(formatter, "{} was not found in the PATH ({})", name, path)
+            }
+            _ => {
+                let out = format!("{:?}", self);
+                formatter.write_str(&out)
+            }
+        }
     }
 }
 
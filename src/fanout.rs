@@ -0,0 +1,326 @@
+//! Requests sent to several servers handling the same document, e.g. a language server and a
+//! linter. The results of the servers supporting a request are merged, and each item remembers
+//! the server it came from so that it is resolved or executed there.
+use code_actions::{CodeAction, CodeActionOrCommand};
+use completion::CompletionResult;
+use diagnostics::{DocumentDiagnosticParams, DocumentDiagnosticReport};
+use error::Error;
+use futures::Future;
+use futures::future;
+use languageserver_types::{CodeActionParams, CompletionItem, Diagnostic,
+                           TextDocumentPositionParams};
+use markup::Hover;
+use messages::ResponseError;
+use serde_json as json;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::rc::Rc;
+use types::{REQUEST__CodeAction, REQUEST__Completion, REQUEST__Hover};
+use LanguageServer;
+
+/// An item returned by the server at index `origin` in the `Fanout`.
+#[derive(Clone, Debug)]
+pub struct Tagged<T> {
+    pub origin: usize,
+    pub item: T,
+}
+
+struct Member {
+    server: Rc<RefCell<LanguageServer>>,
+    priority: i32,
+}
+
+pub struct Fanout {
+    members: Vec<Member>,
+    dedup: bool,
+}
+
+impl Fanout {
+    pub fn new() -> Self {
+        Fanout {
+            members: Vec::new(),
+            dedup: true,
+        }
+    }
+
+    /// Adds a server, and returns its index. The items of servers with a higher priority come
+    /// first, and win over their duplicates; servers with the same priority keep the order they
+    /// were added in.
+    pub fn add(&mut self, server: Rc<RefCell<LanguageServer>>, priority: i32) -> usize {
+        self.members.push(Member {
+            server: server,
+            priority: priority,
+        });
+        self.members.len() - 1
+    }
+
+    /// Whether duplicate items are dropped, which they are by default. Completion items are
+    /// duplicates when they have the same label and inserted text, code actions when they have
+    /// the same title, hovers when they have the same text, and diagnostics when they have the
+    /// same range and message.
+    pub fn dedup(&mut self, dedup: bool) -> &mut Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// The server at an index returned by `add`, or found in `Tagged::origin`.
+    pub fn server(&self, origin: usize) -> Rc<RefCell<LanguageServer>> {
+        self.members[origin].server.clone()
+    }
+
+    /// Sends a request to the servers advertising `capability`. Servers failing to answer are
+    /// left out with a warning rather than failing the whole request.
+    fn fan_out<F, R, T>(&self, capability: &'static str, request: F) -> impl 'static + Future<Item=Vec<(usize, T)>, Error=Error>
+        where F: Fn(&mut LanguageServer) -> R,
+              R: 'static + Future<Item=Result<T, ResponseError<()>>, Error=Error>,
+              T: 'static
+    {
+        let requests: Vec<_> = self.members
+            .iter()
+            .enumerate()
+            .filter(|&(_, member)| member.server.borrow().capabilities.is_enabled(capability))
+            .map(|(origin, member)| {
+                let requested = request(&mut member.server.borrow_mut());
+                requested.then(move |result| -> Result<Option<(usize, T)>, Error> {
+                    Ok(match result {
+                        Ok(Ok(value)) => Some((origin, value)),
+                        Ok(Err(err)) => {
+                            warn!("server {} failed to answer for {}: {}", origin, capability, err.message());
+                            None
+                        }
+                        Err(err) => {
+                            warn!("server {} failed to answer for {}: {:?}", origin, capability, err);
+                            None
+                        }
+                    })
+                })
+            })
+            .collect();
+        future::join_all(requests).map(|results| results.into_iter().filter_map(|result| result).collect())
+    }
+
+    fn priorities(&self) -> Vec<i32> {
+        self.members.iter().map(|member| member.priority).collect()
+    }
+
+    /// The completion items of all the servers, and whether any of the lists is incomplete.
+    pub fn completion(&self, params: TextDocumentPositionParams) -> impl 'static + Future<Item=(Vec<Tagged<CompletionItem>>, bool), Error=Error>
+    {
+        let priorities = self.priorities();
+        let dedup = self.dedup;
+        let params = json::to_value(&params);
+        self.fan_out("/completionProvider", move |server| {
+                server.call_with_params::<_, CompletionResult, ()>(REQUEST__Completion, params.clone())
+            })
+            .map(move |results| merge_completions(results, &priorities, dedup))
+    }
+
+    /// The code actions of all the servers. Servers answering `null` have no actions.
+    pub fn code_action(&self, params: CodeActionParams) -> impl 'static + Future<Item=Vec<Tagged<CodeActionOrCommand>>, Error=Error>
+    {
+        let priorities = self.priorities();
+        let dedup = self.dedup;
+        let params = json::to_value(&params);
+        self.fan_out("/codeActionProvider", move |server| {
+                server.call_with_params::<_, Option<Vec<CodeActionOrCommand>>, ()>(REQUEST__CodeAction, params.clone())
+            })
+            .map(move |results| {
+                let results = results.into_iter()
+                    .map(|(origin, actions)| (origin, actions.unwrap_or_default()))
+                    .collect();
+                merge(results, &priorities, dedup, |action: &CodeActionOrCommand| action.title().to_string())
+            })
+    }
+
+    /// The hovers of all the servers, to be shown together.
+    pub fn hover(&self, params: TextDocumentPositionParams) -> impl 'static + Future<Item=Vec<Tagged<Hover>>, Error=Error>
+    {
+        let priorities = self.priorities();
+        let dedup = self.dedup;
+        let params = json::to_value(&params);
+        self.fan_out("/hoverProvider", move |server| {
+                server.call_with_params::<_, Option<Hover>, ()>(REQUEST__Hover, params.clone())
+            })
+            .map(move |results| {
+                let results = results.into_iter()
+                    .map(|(origin, hover)| (origin, hover.into_iter().collect()))
+                    .collect();
+                merge(results, &priorities, dedup, |hover: &Hover| hover.markup().value)
+            })
+    }
+
+    /// The pulled diagnostics of all the servers. `previous_result_id` is ignored, since result
+    /// ids are specific to each server.
+    pub fn diagnostics(&self, params: DocumentDiagnosticParams) -> impl 'static + Future<Item=Vec<Tagged<Diagnostic>>, Error=Error>
+    {
+        let priorities = self.priorities();
+        let dedup = self.dedup;
+        let params = json::to_value(&DocumentDiagnosticParams { previous_result_id: None, ..params });
+        self.fan_out("/diagnosticProvider", move |server| {
+                server.call_with_params::<_, DocumentDiagnosticReport, ()>("textDocument/diagnostic", params.clone())
+            })
+            .map(move |results| {
+                let results = results.into_iter()
+                    .map(|(origin, report): (usize, DocumentDiagnosticReport)| (origin, report.items))
+                    .collect();
+                merge(results, &priorities, dedup, diagnostic_key)
+            })
+    }
+
+    /// Resolves a completion item on the server it came from.
+    pub fn resolve_completion_item(&self, item: Tagged<CompletionItem>) -> impl 'static + Future<Item=Result<Tagged<CompletionItem>, ResponseError<()>>, Error=Error>
+    {
+        let origin = item.origin;
+        let server = self.server(origin);
+        let resolved = server.borrow_mut().resolve_completion_item(item.item);
+        resolved.map(move |result| result.map(|item| Tagged { origin: origin, item: item }))
+    }
+
+    /// Resolves a code action on the server it came from.
+    pub fn resolve_code_action(&self, action: Tagged<CodeAction>) -> impl 'static + Future<Item=Result<Tagged<CodeAction>, ResponseError<()>>, Error=Error>
+    {
+        let origin = action.origin;
+        let server = self.server(origin);
+        let resolved = server.borrow_mut().resolve_code_action(action.item);
+        resolved.map(move |result| result.map(|action| Tagged { origin: origin, item: action }))
+    }
+
+    /// Applies a code action with the server it came from, which runs its command.
+    pub fn apply_code_action(&self, action: Tagged<CodeActionOrCommand>) -> impl 'static + Future<Item=Result<json::Value, ResponseError<()>>, Error=Error>
+    {
+        let server = self.server(action.origin);
+        let applied = server.borrow_mut().apply_code_action(action.item);
+        applied
+    }
+}
+
+/// Merges the completion lists of the servers, which are incomplete if any of them is.
+fn merge_completions(results: Vec<(usize, CompletionResult)>, priorities: &[i32], dedup: bool) -> (Vec<Tagged<CompletionItem>>, bool) {
+    let mut is_incomplete = false;
+    let results = results.into_iter()
+        .map(|(origin, result)| {
+            let (items, incomplete) = result.into_items();
+            is_incomplete = is_incomplete || incomplete;
+            (origin, items)
+        })
+        .collect();
+    (merge(results, priorities, dedup, completion_key), is_incomplete)
+}
+
+fn completion_key(item: &CompletionItem) -> (String, Option<String>) {
+    let inserted = item.text_edit
+        .as_ref()
+        .map(|edit| edit.new_text.clone())
+        .or_else(|| item.insert_text.clone());
+    (item.label.clone(), inserted)
+}
+
+fn diagnostic_key(diagnostic: &Diagnostic) -> (u64, u64, u64, u64, String) {
+    let range = &diagnostic.range;
+    (range.start.line,
+     range.start.character,
+     range.end.line,
+     range.end.character,
+     diagnostic.message.clone())
+}
+
+/// Tags the items of each server with its index, orders them by the priority of their server,
+/// and drops the duplicates of items from higher priority servers if `dedup` is set.
+fn merge<T, K, F>(results: Vec<(usize, Vec<T>)>, priorities: &[i32], dedup: bool, key: F) -> Vec<Tagged<T>>
+    where K: Eq + Hash,
+          F: Fn(&T) -> K
+{
+    let mut results = results;
+    // The sort is stable, so servers with the same priority stay in order.
+    results.sort_by(|&(a, _), &(b, _)| priorities[b].cmp(&priorities[a]));
+
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    for (origin, items) in results {
+        for item in items {
+            if dedup && !seen.insert(key(&item)) {
+                continue;
+            }
+            merged.push(Tagged {
+                origin: origin,
+                item: item,
+            });
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_orders_by_priority_and_drops_duplicates() {
+        let results = vec![(0, vec!["unused", "shadowed"]),
+                           (1, vec!["shadowed", "typo"]),
+                           (2, vec!["style"])];
+        let priorities = [0, 10, 0];
+
+        let merged = merge(results.clone(), &priorities, true, |item: &&str| item.to_string());
+        let tagged: Vec<(usize, &str)> = merged.iter().map(|tagged| (tagged.origin, tagged.item)).collect();
+        assert_eq!(tagged,
+                   vec![(1, "shadowed"), (1, "typo"), (0, "unused"), (2, "style")]);
+
+        let merged = merge(results, &priorities, false, |item: &&str| item.to_string());
+        assert_eq!(merged.len(), 5);
+    }
+
+    fn completions(text: &str) -> CompletionResult {
+        json::from_str(text).unwrap()
+    }
+
+    fn labels(items: &[Tagged<CompletionItem>]) -> Vec<(usize, String)> {
+        items.iter().map(|tagged| (tagged.origin, tagged.item.label.clone())).collect()
+    }
+
+    #[test]
+    fn completions_are_duplicates_when_they_insert_the_same_text() {
+        let results = vec![(0, completions(r#"[{"label": "push", "insertText": "push($1)"}, {"label": "pop"}]"#)),
+                           (1, completions(r#"[{"label": "push", "insertText": "push"}, {"label": "pop"}]"#))];
+        let (items, is_incomplete) = merge_completions(results, &[0, 0], true);
+        assert_eq!(labels(&items),
+                   vec![(0, "push".to_string()), (0, "pop".to_string()), (1, "push".to_string())]);
+        assert!(!is_incomplete);
+    }
+
+    #[test]
+    fn completions_are_incomplete_if_any_list_is() {
+        let results = vec![(0, completions(r#"[{"label": "len"}]"#)),
+                           (1, completions(r#"{"isIncomplete": true, "items": [{"label": "length"}]}"#)),
+                           (2, completions(r#"{"isIncomplete": false, "items": []}"#))];
+        let (items, is_incomplete) = merge_completions(results, &[0, 0, 0], true);
+        assert_eq!(items.len(), 2);
+        assert!(is_incomplete);
+
+        let (_, is_incomplete) = merge_completions(vec![(0, completions("null"))], &[0], true);
+        assert!(!is_incomplete);
+    }
+
+    #[test]
+    fn diagnostics_are_duplicates_when_they_have_the_same_range_and_message() {
+        let diagnostics = |text: &str| -> Vec<Diagnostic> { json::from_str(text).unwrap() };
+        let range = r#""range": {"start": {"line": 1, "character": 4}, "end": {"line": 1, "character": 9}}"#;
+        let other_range = r#""range": {"start": {"line": 2, "character": 4}, "end": {"line": 2, "character": 9}}"#;
+        let results = vec![(0, diagnostics(&format!(r#"[{{{}, "message": "unused variable", "source": "rustc"}}]"#, range))),
+                           (1, diagnostics(&format!(r#"[{{{}, "message": "unused variable", "source": "clippy"}},
+                                                       {{{}, "message": "unused variable"}},
+                                                       {{{}, "message": "needless borrow"}}]"#,
+                                                    range,
+                                                    other_range,
+                                                    range)))];
+        let merged = merge(results, &[0, 0], true, diagnostic_key);
+        let kept: Vec<(usize, u64, String)> = merged.iter()
+            .map(|tagged| (tagged.origin, tagged.item.range.start.line, tagged.item.message.clone()))
+            .collect();
+        assert_eq!(kept,
+                   vec![(0, 1, "unused variable".to_string()),
+                        (1, 2, "unused variable".to_string()),
+                        (1, 1, "needless borrow".to_string())]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Folding ranges, and a model nesting them the way an editor displays them.
+use languageserver_types::TextDocumentIdentifier;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FoldingRangeParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FoldingRangeKind {
+    Comment,
+    Imports,
+    Region,
+    Other(String),
+}
+
+impl Deserialize for FoldingRangeKind {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        let kind = String::deserialize(deserializer)?;
+        Ok(match kind.as_str() {
+            "comment" => FoldingRangeKind::Comment,
+            "imports" => FoldingRangeKind::Imports,
+            "region" => FoldingRangeKind::Region,
+            _ => FoldingRangeKind::Other(kind.clone()),
+        })
+    }
+}
+
+impl Serialize for FoldingRangeKind {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        match *self {
+            FoldingRangeKind::Comment => serializer.serialize_str("comment"),
+            FoldingRangeKind::Imports => serializer.serialize_str("imports"),
+            FoldingRangeKind::Region => serializer.serialize_str("region"),
+            FoldingRangeKind::Other(ref kind) => serializer.serialize_str(kind),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FoldingRange {
+    #[serde(rename = "startLine")]
+    pub start_line: u64,
+    #[serde(rename = "startCharacter", skip_serializing_if = "Option::is_none")]
+    pub start_character: Option<u64>,
+    #[serde(rename = "endLine")]
+    pub end_line: u64,
+    #[serde(rename = "endCharacter", skip_serializing_if = "Option::is_none")]
+    pub end_character: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<FoldingRangeKind>,
+    #[serde(rename = "collapsedText", skip_serializing_if = "Option::is_none")]
+    pub collapsed_text: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fold {
+    pub start_line: u64,
+    pub end_line: u64,
+    pub kind: Option<FoldingRangeKind>,
+    pub collapsed_text: Option<String>,
+    pub children: Vec<Fold>,
+}
+
+impl Fold {
+    fn contains_line(&self, line: u64) -> bool {
+        self.start_line <= line && line <= self.end_line
+    }
+}
+
+/// Folding ranges nested into a tree. Since an editor shows a single fold marker per line, only
+/// one range is kept per start line: the outermost one, with the first kind given for it.
+/// Ranges that span a single line or that overlap without nesting are dropped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoldingModel {
+    roots: Vec<Fold>,
+}
+
+impl FoldingModel {
+    pub fn new(mut ranges: Vec<FoldingRange>) -> Self {
+        ranges.retain(|range| range.end_line > range.start_line);
+        ranges.sort_by(|a, b| (a.start_line, b.end_line).cmp(&(b.start_line, a.end_line)));
+
+        let mut folds: Vec<Fold> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            if let Some(last) = folds.last_mut() {
+                if last.start_line == range.start_line {
+                    if last.kind.is_none() && last.end_line == range.end_line {
+                        last.kind = range.kind;
+                    }
+                    continue;
+                }
+            }
+            folds.push(Fold {
+                start_line: range.start_line,
+                end_line: range.end_line,
+                kind: range.kind,
+                collapsed_text: range.collapsed_text,
+                children: Vec::new(),
+            });
+        }
+
+        let mut roots = Vec::new();
+        let mut stack: Vec<Fold> = Vec::new();
+        for fold in folds {
+            while stack.last().map_or(false, |open| open.end_line < fold.start_line) {
+                close_fold(&mut stack, &mut roots);
+            }
+            if stack.last().map_or(false, |open| open.end_line < fold.end_line) {
+                debug!("dropping overlapping folding range {:?}", fold);
+                continue;
+            }
+            stack.push(fold);
+        }
+        while !stack.is_empty() {
+            close_fold(&mut stack, &mut roots);
+        }
+
+        FoldingModel { roots: roots }
+    }
+
+    pub fn roots(&self) -> &[Fold] {
+        &self.roots
+    }
+
+    /// The folds containing a line, from the outermost to the innermost.
+    pub fn folds_at(&self, line: u64) -> Vec<&Fold> {
+        let mut folds = Vec::new();
+        let mut level = &self.roots;
+        while let Some(fold) = level.iter().find(|fold| fold.contains_line(line)) {
+            folds.push(fold);
+            level = &fold.children;
+        }
+        folds
+    }
+
+    pub fn innermost_at(&self, line: u64) -> Option<&Fold> {
+        self.folds_at(line).pop()
+    }
+
+    /// The fold starting on a line, to toggle from its marker.
+    pub fn starting_at(&self, line: u64) -> Option<&Fold> {
+        self.folds_at(line).into_iter().find(|fold| fold.start_line == line)
+    }
+
+    /// All the folds of a kind, e.g. to collapse every comment at once.
+    pub fn of_kind(&self, kind: &FoldingRangeKind) -> Vec<&Fold> {
+        let mut found = Vec::new();
+        let mut pending: Vec<&Fold> = self.roots.iter().rev().collect();
+        while let Some(fold) = pending.pop() {
+            if fold.kind.as_ref() == Some(kind) {
+                found.push(fold);
+            }
+            pending.extend(fold.children.iter().rev());
+        }
+        found
+    }
+}
+
+fn close_fold(stack: &mut Vec<Fold>, roots: &mut Vec<Fold>) {
+    if let Some(fold) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(fold),
+            None => roots.push(fold),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn range(start_line: u64, end_line: u64, kind: Option<FoldingRangeKind>) -> FoldingRange {
+        FoldingRange {
+            start_line: start_line,
+            start_character: None,
+            end_line: end_line,
+            end_character: None,
+            kind: kind,
+            collapsed_text: None,
+        }
+    }
+
+    #[test]
+    fn folding_model_nests_and_deduplicates() {
+        let model = FoldingModel::new(vec![range(10, 20, None),
+                                           range(0, 3, Some(FoldingRangeKind::Imports)),
+                                           range(12, 14, None),
+                                           range(12, 14, Some(FoldingRangeKind::Comment)),
+                                           range(10, 15, None),
+                                           range(13, 25, None),
+                                           range(16, 16, None)]);
+
+        assert_eq!(model.roots().len(), 2);
+        assert_eq!(model.roots()[1].start_line, 10);
+        assert_eq!(model.roots()[1].end_line, 20);
+        assert_eq!(model.roots()[1].children.len(), 1);
+
+        let innermost = model.innermost_at(13).unwrap();
+        assert_eq!((innermost.start_line, innermost.end_line), (12, 14));
+        assert_eq!(innermost.kind, Some(FoldingRangeKind::Comment));
+        assert_eq!(model.folds_at(13).len(), 2);
+        assert_eq!(model.starting_at(12), Some(innermost));
+        assert_eq!(model.of_kind(&FoldingRangeKind::Imports)[0].start_line, 0);
+    }
+}
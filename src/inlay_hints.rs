@@ -0,0 +1,241 @@
+//! Inlay hints, as described in the 3.17 version of the protocol, and a cache so that they are
+//! not requested again while scrolling through an unchanged document.
+use documents::{range_contains, range_contains_range, ranges_overlap};
+use languageserver_types::{Command, Location, Position, Range, TextDocumentIdentifier, TextEdit};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json as json;
+use std::collections::HashMap;
+use utils::from_json;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InlayHintParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InlayHintKind {
+    Type, // 1
+    Parameter, // 2
+}
+
+impl Deserialize for InlayHintKind {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        use serde::de::Error;
+        match u8::deserialize(deserializer)? {
+            1 => Ok(InlayHintKind::Type),
+            2 => Ok(InlayHintKind::Parameter),
+            other => Err(D::Error::custom(format!("unknown inlay hint kind {}", other))),
+        }
+    }
+}
+
+impl Serialize for InlayHintKind {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        match *self {
+            InlayHintKind::Type => serializer.serialize_u8(1),
+            InlayHintKind::Parameter => serializer.serialize_u8(2),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InlayHintLabelPart {
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug)]
+pub enum InlayHintLabel {
+    String(String),
+    Parts(Vec<InlayHintLabelPart>),
+}
+
+impl Deserialize for InlayHintLabel {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        match json::Value::deserialize(deserializer)? {
+            json::Value::String(label) => Ok(InlayHintLabel::String(label)),
+            parts => from_json(parts).map(InlayHintLabel::Parts),
+        }
+    }
+}
+
+impl Serialize for InlayHintLabel {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        match *self {
+            InlayHintLabel::String(ref label) => label.serialize(serializer),
+            InlayHintLabel::Parts(ref parts) => parts.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InlayHint {
+    pub position: Position,
+    pub label: InlayHintLabel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<InlayHintKind>,
+    #[serde(rename = "textEdits", skip_serializing_if = "Option::is_none")]
+    pub text_edits: Option<Vec<TextEdit>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<json::Value>,
+    #[serde(rename = "paddingLeft", skip_serializing_if = "Option::is_none")]
+    pub padding_left: Option<bool>,
+    #[serde(rename = "paddingRight", skip_serializing_if = "Option::is_none")]
+    pub padding_right: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<json::Value>,
+}
+
+impl InlayHint {
+    /// The label as displayed, with its parts concatenated.
+    pub fn label_text(&self) -> String {
+        match self.label {
+            InlayHintLabel::String(ref label) => label.clone(),
+            InlayHintLabel::Parts(ref parts) => {
+                parts.iter().map(|part| part.value.as_str()).collect::<Vec<_>>().concat()
+            }
+        }
+    }
+}
+
+struct CachedHints {
+    version: u64,
+    range: Range,
+    hints: Vec<InlayHint>,
+}
+
+/// Inlay hints by document, for the document version and range they were requested for.
+pub struct InlayHintCache {
+    by_uri: HashMap<String, Vec<CachedHints>>,
+}
+
+impl InlayHintCache {
+    pub fn new() -> Self {
+        InlayHintCache { by_uri: HashMap::new() }
+    }
+
+    /// The hints within `range`, if they were requested for a range containing it at that
+    /// version of the document.
+    pub fn get(&self, uri: &str, version: u64, range: &Range) -> Option<Vec<InlayHint>> {
+        self.by_uri
+            .get(uri)
+            .and_then(|entries| {
+                entries.iter().find(|entry| {
+                    entry.version == version && range_contains_range(&entry.range, range)
+                })
+            })
+            .map(|entry| {
+                entry.hints
+                    .iter()
+                    .filter(|hint| range_contains(range, &hint.position))
+                    .cloned()
+                    .collect()
+            })
+    }
+
+    /// Cache hints, dropping those cached for other versions of the document, and those cached
+    /// for ranges overlapping `range`, which they replace.
+    pub fn insert(&mut self, uri: String, version: u64, range: Range, hints: Vec<InlayHint>) {
+        let entries = self.by_uri.entry(uri).or_insert_with(Vec::new);
+        entries.retain(|entry| entry.version == version && !ranges_overlap(&entry.range, &range));
+        entries.push(CachedHints {
+            version: version,
+            range: range,
+            hints: hints,
+        });
+    }
+
+    pub fn invalidate(&mut self, uri: &str) {
+        self.by_uri.remove(uri);
+    }
+
+    pub fn clear(&mut self) {
+        self.by_uri.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn range(start_line: u64, end_line: u64) -> Range {
+        Range {
+            start: Position { line: start_line, character: 0 },
+            end: Position { line: end_line, character: 0 },
+        }
+    }
+
+    fn hint(line: u64, label: &str) -> InlayHint {
+        json::from_str(&format!(r#"{{"position": {{"line": {}, "character": 4}}, "label": "{}"}}"#, line, label))
+            .unwrap()
+    }
+
+    fn labels(hints: Option<Vec<InlayHint>>) -> Option<Vec<String>> {
+        hints.map(|hints| hints.iter().map(InlayHint::label_text).collect())
+    }
+
+    #[test]
+    fn cached_hints_are_returned_within_the_requested_range() {
+        let mut cache = InlayHintCache::new();
+        cache.insert("file:///main.rs".to_string(), 3, range(0, 100), vec![hint(10, "i32"), hint(50, "&str")]);
+
+        assert_eq!(labels(cache.get("file:///main.rs", 3, &range(0, 20))), Some(vec!["i32".to_string()]));
+        assert_eq!(labels(cache.get("file:///main.rs", 3, &range(0, 100))),
+                   Some(vec!["i32".to_string(), "&str".to_string()]));
+        assert!(cache.get("file:///other.rs", 3, &range(0, 20)).is_none());
+    }
+
+    #[test]
+    fn new_versions_miss_the_cache() {
+        let mut cache = InlayHintCache::new();
+        cache.insert("file:///main.rs".to_string(), 3, range(0, 100), vec![hint(10, "i32")]);
+        assert!(cache.get("file:///main.rs", 4, &range(0, 20)).is_none());
+
+        // Caching the new version drops the hints of the previous one.
+        cache.insert("file:///main.rs".to_string(), 4, range(0, 20), vec![hint(10, "u8")]);
+        assert!(cache.get("file:///main.rs", 3, &range(0, 20)).is_none());
+        assert_eq!(labels(cache.get("file:///main.rs", 4, &range(0, 20))), Some(vec!["u8".to_string()]));
+    }
+
+    #[test]
+    fn ranges_outside_the_cached_range_miss_the_cache() {
+        let mut cache = InlayHintCache::new();
+        cache.insert("file:///main.rs".to_string(), 3, range(0, 100), vec![hint(10, "i32")]);
+        assert!(cache.get("file:///main.rs", 3, &range(90, 120)).is_none());
+        assert!(cache.get("file:///main.rs", 3, &range(200, 220)).is_none());
+
+        cache.invalidate("file:///main.rs");
+        assert!(cache.get("file:///main.rs", 3, &range(0, 20)).is_none());
+    }
+
+    #[test]
+    fn hints_for_overlapping_ranges_replace_each_other() {
+        let mut cache = InlayHintCache::new();
+        for _ in 0..3 {
+            cache.insert("file:///main.rs".to_string(), 3, range(0, 100), vec![hint(10, "i32")]);
+        }
+        cache.insert("file:///main.rs".to_string(), 3, range(200, 300), vec![hint(250, "u8")]);
+        assert_eq!(cache.by_uri["file:///main.rs"].len(), 2);
+
+        cache.insert("file:///main.rs".to_string(), 3, range(50, 150), vec![hint(60, "bool")]);
+        assert_eq!(cache.by_uri["file:///main.rs"].len(), 2);
+        assert!(cache.get("file:///main.rs", 3, &range(0, 20)).is_none());
+        assert_eq!(labels(cache.get("file:///main.rs", 3, &range(50, 150))), Some(vec!["bool".to_string()]));
+        assert_eq!(labels(cache.get("file:///main.rs", 3, &range(200, 300))), Some(vec!["u8".to_string()]));
+    }
+}
@@ -1,4 +1,60 @@
+use serde_json as json;
+use std::path::{Path, PathBuf};
+
+/// How to start and set up the server for a language. Only `get_command` is required: the other
+/// hooks have defaults suitable for most servers.
 pub trait Language {
     /// Return a list of command arguments to be spawned.
     fn get_command(&self) -> Vec<String>;
+
+    /// Environment variables set for the server, on top of the ones of the current process.
+    fn env(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// The directory the server is started in, or the current directory if `None`.
+    fn working_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// The files marking the root of a project, by priority: the root of a file is the nearest
+    /// directory containing the first marker found.
+    fn root_markers(&self) -> Vec<String> {
+        vec![".git".to_string()]
+    }
+
+    /// The `initializationOptions` sent in the initialize request, unless the caller gives its
+    /// own.
+    fn initialization_options(&self) -> Option<json::Value> {
+        None
+    }
+
+    /// The language ids of the documents handled by the server, by file extension, e.g.
+    /// `("rs", "rust")`.
+    fn language_ids(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// The language id of a file, from its extension.
+    fn language_id(&self, path: &Path) -> Option<String> {
+        let extension = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => extension,
+            None => return None,
+        };
+        self.language_ids()
+            .into_iter()
+            .find(|&(ref handled, _)| handled == extension)
+            .map(|(_, language_id)| language_id)
+    }
+
+    /// The initial settings answered to `workspace/configuration` requests.
+    fn settings(&self) -> Option<json::Value> {
+        None
+    }
+
+    /// The client capabilities advertised in the initialize request, unless the caller gives
+    /// its own. `defaults` are the capabilities this client implements.
+    fn client_capabilities(&self, defaults: json::Value) -> json::Value {
+        defaults
+    }
 }
@@ -86,10 +86,11 @@ pub struct AsyncChildIo {
 }
 
 impl AsyncChildIo {
-    pub fn new(child: Child, handle: &Handle) -> Result<Self> {
-        let raw_stdin = Stdin(child.stdin.unwrap());
+    /// Takes over the pipes of the child, which stays with the caller so that it can be killed.
+    pub fn new(child: &mut Child, handle: &Handle) -> Result<Self> {
+        let raw_stdin = Stdin(child.stdin.take().unwrap());
         let stdin = PollEvented::new(raw_stdin, handle)?;
-        let raw_stdout = Stdout(child.stdout.unwrap());
+        let raw_stdout = Stdout(child.stdout.take().unwrap());
         let stdout = PollEvented::new(raw_stdout, handle)?;
         Ok(AsyncChildIo {
             stdin: stdin,
@@ -247,14 +248,14 @@ mod test {
     #[test]
     fn async_child_io_does_not_hang() {
         drop(env_logger::init());
-        let child = Command::new("cat")
+        let mut child = Command::new("cat")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
             .unwrap();
 
         let mut core = Core::new().unwrap();
-        let (read, write) = AsyncChildIo::new(child, &core.handle()).unwrap().split();
+        let (read, write) = AsyncChildIo::new(&mut child, &core.handle()).unwrap().split();
 
         let w = WritePoller {
             count: 0,
@@ -311,7 +312,7 @@ mod test {
     #[test]
     fn async_child_io_can_be_framed() {
         drop(env_logger::init());
-        let child = Command::new("cat")
+        let mut child = Command::new("cat")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
@@ -319,7 +320,7 @@ mod test {
 
         let mut core = Core::new().unwrap();
         let (sink, stream) =
-            AsyncChildIo::new(child, &core.handle()).unwrap().framed(UpcaseCodec).split();
+            AsyncChildIo::new(&mut child, &core.handle()).unwrap().framed(UpcaseCodec).split();
 
         let lowercase: Vec<Result<String>> = vec!["abc\n", "def\n", "ghi\n", "jkl\n"]
             .into_iter()
@@ -5,77 +5,195 @@
 #[macro_use]
 extern crate chomp;
 extern crate futures;
+extern crate glob;
 extern crate languageserver_types;
 extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate mio;
+extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
 extern crate tokio_core;
 extern crate tokio_service;
+extern crate toml;
+extern crate url;
 extern crate uuid;
 
+mod capabilities;
+mod call_hierarchy;
 mod client;
+mod code_actions;
+mod code_lens;
 mod codec;
+mod colors;
+mod commands;
+mod completion;
+mod configuration;
+mod diagnostics;
+mod diff;
 mod dispatcher;
+mod documents;
+mod inlay_hints;
 mod error;
 mod evented_receiver;
+mod fanout;
+mod folding;
 mod language;
 mod language_server_io;
+mod linked_editing;
+mod markup;
 mod message_parser;
 mod messages;
+pub mod presets;
+mod rename;
+mod roots;
+mod selection;
+mod semantic_tokens;
+mod server_config;
+mod server_requests;
+mod signature_help;
+pub mod snippet;
+mod supervisor;
+mod symbols;
+mod type_hierarchy;
 mod utils;
+mod workspace;
 
 pub mod types {
     pub use languageserver_types::*;
-
-    #[derive(Deserialize, Debug)]
-    pub enum CompletionResult {
-        CompletionList(CompletionList),
-        CompletionItems(Vec<CompletionItem>),
-    }
+    pub use call_hierarchy::{CallHierarchyIncomingCall, CallHierarchyIncomingCallsParams,
+                             CallHierarchyItem, CallHierarchyOutgoingCall,
+                             CallHierarchyOutgoingCallsParams};
+    pub use code_actions::{CodeAction, CodeActionOrCommand};
+    pub use colors::{Color, ColorInformation, ColorPresentation, ColorPresentationParams,
+                     DocumentColorParams};
+    pub use completion::CompletionResult;
+    pub use diagnostics::{DocumentDiagnosticParams, DocumentDiagnosticReport};
+    pub use folding::{FoldingRange, FoldingRangeKind, FoldingRangeParams};
+    pub use inlay_hints::{InlayHint, InlayHintKind, InlayHintLabel, InlayHintLabelPart,
+                          InlayHintParams};
+    pub use linked_editing::LinkedEditingRanges;
+    pub use markup::Hover;
+    pub use rename::PrepareRenameResponse;
+    pub use selection::{SelectionRange, SelectionRangeParams};
+    pub use signature_help::{ParameterInformation, ParameterLabel, SignatureHelp,
+                             SignatureHelpContext, SignatureHelpParams, SignatureHelpTriggerKind,
+                             SignatureInformation};
+    pub use symbols::{DocumentSymbol, DocumentSymbolResponse};
+    pub use type_hierarchy::{TypeHierarchyItem, TypeHierarchySubtypesParams,
+                             TypeHierarchySupertypesParams};
+    pub use semantic_tokens::{SemanticToken, SemanticTokens, SemanticTokensDelta,
+                              SemanticTokensDeltaParams, SemanticTokensEdit,
+                              SemanticTokensFullDeltaResult, SemanticTokensLegend,
+                              SemanticTokensParams, SemanticTokensRangeParams};
 
     #[derive(Deserialize, Debug)]
     pub enum LocationOrLocationList {
         Location(Location),
         Locations(Vec<Location>)
     }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ExecuteCommandParams {
+        pub command: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub arguments: Option<Vec<::serde_json::Value>>,
+    }
 }
 
+pub use call_hierarchy::{CallDirection, CallHierarchyTree, CallNode, NodeId};
+pub use capabilities::ClientCapabilitiesBuilder;
+pub use code_actions::{filter_by_kinds, kind_matches, kinds as code_action_kinds};
+pub use commands::CommandRegistry;
+pub use completion::{fuzzy_score, CompletionSession, Insertion};
+pub use configuration::Settings;
+pub use documents::{apply_text_edits, OpenDocument};
+pub use fanout::{Fanout, Tagged};
+pub use folding::{Fold, FoldingModel};
 pub use language::Language;
+pub use linked_editing::LinkedEditing;
+pub use markup::{completion_documentation, Markup, MarkupFormat, RenderStyle};
+pub use messages::{RpcError, ServerNotification};
+pub use rename::{FileDiff, RenameTarget};
+pub use selection::SelectionWalker;
+pub use server_config::{ConfiguredLanguage, ServerConfig, ServersConfig};
+pub use signature_help::SignatureHelpSession;
+pub use supervisor::{RestartPolicy, Supervisor};
+pub use symbols::{OutlineNode, OutlineTree};
+pub use type_hierarchy::{TypeGraph, TypeHierarchyDirection};
+pub use workspace::Workspace;
 
 use evented_receiver::EventedReceiver;
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
 use error::{Error, Result as CustomResult};
 use tokio_core::reactor::{Handle, PollEvented};
 use language_server_io::AsyncChildIo;
-use client::RpcClient;
-use messages::{ServerNotification, Notification, RequestMessage, IncomingMessage, ResponseError};
-use tokio_service::Service;
+use capabilities::{Capabilities, ClientCapabilitiesBuilder};
+use client::{MessageHandle, RpcClient};
+use code_lens::CodeLensCache;
+use documents::Documents;
+use inlay_hints::InlayHintCache;
+use messages::{Notification, IncomingMessage, ResponseError, ServerResponse};
+use server_requests::ServerRequestHandlers;
 use futures::stream::Stream;
 use serde_json as json;
+use serde_json::builder::ObjectBuilder;
 use codec::RpcCodec;
 use tokio_core::io::Io;
 use futures::Future;
+use futures::future::{self, Either};
+use futures::sync::oneshot;
 use types::*;
-use utils::handle_response;
+use utils::call_with_params;
 use serde::{Serialize, Deserialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::env;
+use std::rc::Rc;
 
 pub trait RpcFuture<R, E>: Future<Item=Result<R, E>, Error=Error> {}
 impl<R, E> RpcFuture<R, E> for Future<Item=Result<R, E>, Error=Error> {}
 
 pub struct LanguageServer {
+    language: Rc<Language>,
+    /// The project root the server was started for, if any.
+    root: Option<PathBuf>,
+    /// The roots of the workspace folders, answered to `workspace/workspaceFolders` requests.
+    workspace_folders: Rc<RefCell<Vec<PathBuf>>>,
     client: RpcClient,
     pub notifications: Box<Stream<Item = ServerNotification, Error = Error>>,
+    capabilities: Capabilities,
+    commands: Rc<RefCell<CommandRegistry>>,
+    documents: Rc<RefCell<Documents>>,
+    /// The last semantic tokens received for each document, to which deltas are applied.
+    semantic_tokens_cache: Rc<RefCell<HashMap<String, SemanticTokens>>>,
+    inlay_hint_cache: Rc<RefCell<InlayHintCache>>,
+    code_lens_cache: Rc<RefCell<CodeLensCache>>,
+    server_requests: Rc<RefCell<ServerRequestHandlers>>,
+    /// The settings returned to `workspace/configuration` requests.
+    settings: Rc<RefCell<Settings>>,
+    /// The client capabilities to advertise, kept in line with the server requests handled.
+    client_capabilities: RefCell<ClientCapabilitiesBuilder>,
+    /// The parameters of the last initialize request, to initialize a restarted server.
+    initialize_params: Option<json::Value>,
+    /// Set when the server closed its output, usually because it exited.
+    exited: Rc<Cell<bool>>,
+    exit_watchers: Rc<RefCell<Vec<oneshot::Sender<()>>>>,
+    /// Set by a `Supervisor`, so that pending requests fail with `Error::ServerRestarted` when
+    /// the server exits.
+    supervised: Rc<Cell<bool>>,
+    process: RefCell<Child>,
+    handle: Handle,
 }
 
 macro_rules! requests {
     ( $( $name:ident: $method:expr, $params:ty, $result:ty, $error:ty, $docstring:expr;)+ )=> {$(
         #[doc=$docstring]
-        pub fn $name(&mut self, params: $params) -> impl 'static + Future<Item=Result<$result, ResponseError<$error>>>
+        pub fn $name(&mut self, params: $params) -> impl 'static + Future<Item=Result<$result, ResponseError<$error>>, Error=Error>
         {
             self.call_with_params($method, params)
         }
@@ -85,7 +203,7 @@ macro_rules! requests {
 macro_rules! client_notifications {
     ( $( $name:ident: $method:expr, $params:ty, $docstring:expr;)+ )=> {$(
         #[doc=$docstring]
-        pub fn $name(&self, params: $params) -> impl 'static + Future<Item=()>
+        pub fn $name(&self, params: $params) -> impl 'static + Future<Item=(), Error=Error>
         {
             self.notify_with_params($method, params)
         }
@@ -93,14 +211,34 @@ macro_rules! client_notifications {
 }
 
 impl LanguageServer {
-    pub fn new<L: Language>(lang: L, handle: Handle) -> CustomResult<Self> {
+    pub fn new<L: Language + 'static>(lang: L, handle: Handle) -> CustomResult<Self> {
+        LanguageServer::spawn(Rc::new(lang), None, handle)
+    }
+
+    /// Starts a server for the project of a file, found with the root markers of the
+    /// `Language`. The server is started in the project root, unless the `Language` says
+    /// otherwise. See `initialize_root`.
+    pub fn for_file<L: Language + 'static>(lang: L, file: &Path, handle: Handle) -> CustomResult<Self> {
+        let root = roots::root_or_parent(file, &lang.root_markers());
+        LanguageServer::spawn(Rc::new(lang), Some(root), handle)
+    }
+
+    fn spawn(lang: Rc<Language>, root: Option<PathBuf>, handle: Handle) -> CustomResult<Self> {
+        let root = root.map(|root| roots::absolute(&root));
         let args = lang.get_command();
-        let child = Command::new(&args[0]).args(&args[1..])
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..])
             .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?;
+            .stdout(Stdio::piped());
+        for (key, value) in lang.env() {
+            command.env(key, value);
+        }
+        if let Some(working_dir) = lang.working_dir().or_else(|| root.clone()) {
+            command.current_dir(working_dir);
+        }
+        let mut child = command.spawn()?;
 
-        let (sink, stream) = AsyncChildIo::new(child, &handle)?.framed(RpcCodec).split();
+        let (sink, stream) = AsyncChildIo::new(&mut child, &handle)?.framed(RpcCodec).split();
 
         let (responses_sender, responses_receiver) = mio::channel::channel();
         let responses = EventedReceiver::new(PollEvented::new(responses_receiver, &handle)?);
@@ -110,6 +248,52 @@ impl LanguageServer {
         let notifications = EventedReceiver::new(PollEvented::new(notifications_receiver,
                                                                   &handle)?);
 
+        let server_requests = Rc::new(RefCell::new(ServerRequestHandlers::new()));
+        let inlay_hint_cache = Rc::new(RefCell::new(InlayHintCache::new()));
+        let code_lens_cache = Rc::new(RefCell::new(CodeLensCache::new()));
+
+        {
+            let cache = inlay_hint_cache.clone();
+            register_refresh(&server_requests,
+                             "workspace/inlayHint/refresh",
+                             notifications_sender.clone(),
+                             ServerNotification::InlayHintRefresh,
+                             move || cache.borrow_mut().clear());
+        }
+        {
+            let cache = code_lens_cache.clone();
+            register_refresh(&server_requests,
+                             "workspace/codeLens/refresh",
+                             notifications_sender.clone(),
+                             ServerNotification::CodeLensRefresh,
+                             move || cache.borrow_mut().clear());
+        }
+
+        let mut client_capabilities = ClientCapabilitiesBuilder::new();
+        client_capabilities.configuration(true).workspace_folders(true);
+        let workspace_folders = Rc::new(RefCell::new(root.iter().cloned().collect::<Vec<_>>()));
+        {
+            let workspace_folders = workspace_folders.clone();
+            server_requests.borrow_mut().register("workspace/workspaceFolders", move |_| {
+                let folders: Vec<json::Value> = workspace_folders.borrow()
+                    .iter()
+                    .filter_map(|folder| roots::workspace_folder(folder))
+                    .collect();
+                Ok(json::Value::Array(folders))
+            });
+        }
+        let settings = Rc::new(RefCell::new(lang.settings().map_or_else(Settings::new, Settings::from_value)));
+        {
+            let settings = settings.clone();
+            server_requests.borrow_mut().register("workspace/configuration", move |params| {
+                Ok(settings.borrow().answer(&params))
+            });
+        }
+
+        let worker_client = client.clone();
+        let worker_requests = server_requests.clone();
+        let worker_handle = handle.clone();
+
         let worker = stream.map_err(Error::from)
             .for_each(move |incoming_message| {
                 match incoming_message {
@@ -118,6 +302,14 @@ impl LanguageServer {
                         responses_sender.send(message)?;
                         Ok(())
                     }
+                    IncomingMessage::Request(request) => {
+                        debug!("answering a request {:?}", request);
+                        let outcome = ServerRequestHandlers::handle(&worker_requests, &request);
+                        let response = ServerResponse::new(request.id, outcome);
+                        worker_handle.spawn(worker_client.respond(response)
+                            .map_err(|err| warn!("could not respond to the server: {:?}", err)));
+                        Ok(())
+                    }
                     IncomingMessage::Notification(notification) => {
                         debug!("pushing a notification {:?}", notification);
                         notifications_sender.send(notification)?;
@@ -128,11 +320,45 @@ impl LanguageServer {
             })
             .map_err(|_| ());
 
-        handle.spawn(worker);
+        let exited = Rc::new(Cell::new(false));
+        let exit_watchers: Rc<RefCell<Vec<oneshot::Sender<()>>>> = Rc::new(RefCell::new(Vec::new()));
+        let supervised = Rc::new(Cell::new(false));
+        let worker_supervised = supervised.clone();
+        let exit_client = client.clone();
+        let worker_exited = exited.clone();
+        let worker_exit_watchers = exit_watchers.clone();
+        handle.spawn(worker.then(move |result| {
+            worker_exited.set(true);
+            if worker_supervised.get() {
+                exit_client.fail_pending_on_restart();
+            }
+            for watcher in worker_exit_watchers.borrow_mut().drain(..) {
+                drop(watcher.send(()));
+            }
+            result
+        }));
 
         let ls = LanguageServer {
+            language: lang,
+            root: root,
+            workspace_folders: workspace_folders,
             client: client,
             notifications: Box::new(notifications),
+            capabilities: Capabilities::new(),
+            commands: Rc::new(RefCell::new(CommandRegistry::new())),
+            documents: Rc::new(RefCell::new(Documents::new())),
+            semantic_tokens_cache: Rc::new(RefCell::new(HashMap::new())),
+            inlay_hint_cache: inlay_hint_cache,
+            code_lens_cache: code_lens_cache,
+            server_requests: server_requests,
+            settings: settings,
+            client_capabilities: RefCell::new(client_capabilities),
+            initialize_params: None,
+            exited: exited,
+            exit_watchers: exit_watchers,
+            supervised: supervised,
+            process: RefCell::new(child),
+            handle: handle,
         };
         Ok(ls)
     }
@@ -142,31 +368,814 @@ impl LanguageServer {
               ERR: Deserialize + 'static,
               REQ: Serialize
     {
+        call_with_params(&mut self.client, method, params)
+    }
+
+    /// Initializes the server, and remembers its capabilities. Missing initialization options
+    /// and client capabilities are provided by the `Language`. The workspace folders of the
+    /// server are the ones of `params`, or its `rootUri`.
+    pub fn initialize(&mut self, params: InitializeParams) -> impl 'static + Future<Item=Result<InitializeResult, ResponseError<InitializeError>>, Error=Error>
+    {
+        self.initialize_with(json::to_value(&params))
+    }
+
+    /// Initializes the server for the project root it was started for with `for_file`, or the
+    /// given root, with `rootUri` and `workspaceFolders` pointing to it.
+    pub fn initialize_root(&mut self, root: Option<&Path>) -> impl 'static + Future<Item=Result<InitializeResult, ResponseError<InitializeError>>, Error=Error>
+    {
+        let root = root.map(roots::absolute)
+            .or_else(|| self.root.clone())
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_else(PathBuf::new);
+        let params = ObjectBuilder::new()
+            .insert("processId", unsafe { libc::getpid() })
+            .insert("rootPath", root.to_string_lossy().into_owned())
+            .insert("rootUri", roots::root_uri(&root))
+            .insert("workspaceFolders", roots::workspace_folders(&root))
+            .build();
+        self.initialize_with(params)
+    }
+
+    fn initialize_with(&mut self, mut params: json::Value) -> impl 'static + Future<Item=Result<InitializeResult, ResponseError<InitializeError>>, Error=Error>
+    {
+        if let Some(params) = params.as_object_mut() {
+            if params.get("initializationOptions").map_or(true, |options| *options == json::Value::Null) {
+                if let Some(options) = self.language.initialization_options() {
+                    params.insert("initializationOptions".to_string(), options);
+                }
+            }
+            if params.get("capabilities").map_or(true, |capabilities| *capabilities == json::Value::Null) {
+                let defaults = self.client_capabilities.borrow().build();
+                params.insert("capabilities".to_string(), self.language.client_capabilities(defaults));
+            }
+        }
+
+        if let Some(folders) = roots::initialize_folders(&params) {
+            *self.workspace_folders.borrow_mut() = folders;
+        }
+        self.initialize_params = Some(params.clone());
+        let capabilities = self.capabilities.clone();
+        self.call_with_params::<_, json::Value, InitializeError>(REQUEST__Initialize, params)
+            .and_then(move |result| {
+                match result {
+                    Ok(raw) => {
+                        capabilities.set(raw.find("capabilities").cloned());
+                        Ok(Ok(json::from_value(raw)?))
+                    }
+                    Err(err) => Ok(Err(err)),
+                }
+            })
+    }
+
+    /// Kills the server process if it is still running, and waits for it.
+    fn kill(&self) {
+        let mut process = self.process.borrow_mut();
+        if let Err(err) = process.kill() {
+            debug!("could not kill the server: {:?}", err);
+        }
+        if let Err(err) = process.wait() {
+            warn!("could not wait for the server to exit: {:?}", err);
+        }
+    }
+
+    /// Whether the server exited, or at least closed its output. Requests sent to it fail.
+    pub fn has_exited(&self) -> bool {
+        self.exited.get()
+    }
+
+    /// Resolves when the server exits, or closes its output.
+    pub fn on_exit(&self) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        let (sender, receiver) = oneshot::channel();
+        if self.exited.get() {
+            drop(sender.send(()));
+        } else {
+            self.exit_watchers.borrow_mut().push(sender);
+        }
+        receiver.map_err(|_| Error::OOL)
+    }
+
+    /// The project root the server was started for with `for_file`.
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_ref().map(|root| root.as_path())
+    }
+
+    /// Whether a file belongs to one of the workspace folders of the server, so that the server
+    /// can be reused for it rather than starting another one.
+    pub fn can_handle(&self, file: &Path) -> bool {
+        if self.language.language_id(file).is_none() {
+            return false;
+        }
+        let file_root = roots::root_or_parent(file, &self.language.root_markers());
+        self.workspace_folders.borrow().iter().any(|folder| roots::can_reuse(folder, &file_root))
+    }
+
+    /// The roots of the workspace folders of the server.
+    pub fn workspace_folders(&self) -> Vec<PathBuf> {
+        self.workspace_folders.borrow().clone()
+    }
+
+    /// Whether the server handles several workspace folders, and wants to be told when they
+    /// change. Otherwise, another server has to be started for each project.
+    pub fn supports_workspace_folders(&self) -> bool {
+        self.capabilities.lookup("/workspace/workspaceFolders/supported") == Some(json::Value::Bool(true)) &&
+        self.capabilities.is_enabled("/workspace/workspaceFolders/changeNotifications")
+    }
+
+    /// Adds a workspace folder, and notifies the server. Fails with `Error::Unsupported` if the
+    /// server does not support several workspace folders.
+    pub fn add_workspace_folder(&self, root: &Path) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        if self.workspace_folders.borrow().iter().any(|folder| folder == root) {
+            return Either::A(future::ok(()));
+        }
+        Either::B(self.change_workspace_folders(root, true))
+    }
+
+    /// Removes a workspace folder, and notifies the server.
+    pub fn remove_workspace_folder(&self, root: &Path) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        if !self.workspace_folders.borrow().iter().any(|folder| folder == root) {
+            return Either::A(future::ok(()));
+        }
+        Either::B(self.change_workspace_folders(root, false))
+    }
+
+    fn change_workspace_folders(&self, root: &Path, added: bool) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        if !self.supports_workspace_folders() {
+            return Either::A(future::err(Error::Unsupported("workspace/workspaceFolders")));
+        }
+        let folder = match roots::workspace_folder(root) {
+            Some(folder) => folder,
+            None => return Either::A(future::err(Error::InvalidUri(root.to_string_lossy().into_owned()))),
+        };
+
+        let mut folders = self.workspace_folders.borrow_mut();
+        if added {
+            folders.push(root.to_path_buf());
+        } else {
+            folders.retain(|existing| existing != root);
+        }
+        let (added, removed) = if added {
+            (vec![folder], Vec::new())
+        } else {
+            (Vec::new(), vec![folder])
+        };
+        let params = ObjectBuilder::new()
+            .insert_object("event", |event| event.insert("added", added).insert("removed", removed))
+            .build();
+        Either::B(self.client.notify(Notification::new("workspace/didChangeWorkspaceFolders".to_string(), params)))
+    }
+
+    /// Register a handler for requests sent by the server, replacing the previous one if any.
+    /// Requests without a handler are answered with a `MethodNotFound` error.
+    pub fn on_request<F>(&self, method: &str, handler: F)
+        where F: Fn(json::Value) -> Result<json::Value, RpcError> + 'static
+    {
+        self.server_requests.borrow_mut().register(method, handler)
+    }
+
+    /// The settings returned to the server when it asks for its configuration.
+    pub fn settings(&self) -> Settings {
+        self.settings.borrow().clone()
+    }
+
+    /// Updates the settings, and notifies the server of the change.
+    pub fn update_settings<F>(&self, update: F) -> impl 'static + Future<Item=(), Error=Error>
+        where F: FnOnce(&mut Settings)
+    {
+        update(&mut self.settings.borrow_mut());
+        let global = self.settings.borrow().global().clone();
+        self.did_change_configuration(DidChangeConfigurationParams { settings: global })
+    }
+
+    /// Replaces the settings with those of a JSON or TOML file, and notifies the server.
+    pub fn load_settings(&self, path: &Path) -> impl 'static + Future<Item=(), Error=Error> {
+        match Settings::load(path) {
+            Ok(settings) => Either::A(self.update_settings(move |current| *current = settings)),
+            Err(err) => Either::B(future::err(err)),
+        }
+    }
+
+    /// Changes the client capabilities advertised at initialization, e.g. to disable snippets.
+    pub fn configure_client_capabilities<F>(&self, configure: F)
+        where F: FnOnce(&mut ClientCapabilitiesBuilder)
+    {
+        self.warn_if_initialized();
+        configure(&mut self.client_capabilities.borrow_mut());
+    }
+
+    fn warn_if_initialized(&self) {
+        if self.capabilities.get().is_some() {
+            warn!("client capabilities changed after initialization will not be advertised");
+        }
+    }
+
+    /// Applies the edits requested by the server with `workspace/applyEdit`, like
+    /// `apply_workspace_edit` does.
+    pub fn enable_apply_edit(&self) {
+        self.warn_if_initialized();
+        self.client_capabilities.borrow_mut().apply_edit(true);
+
+        let client = self.client.clone();
+        let documents = self.documents.clone();
+        let handle = self.handle.clone();
+        self.on_request("workspace/applyEdit", move |params| {
+            let edit = params.find("edit")
+                .cloned()
+                .ok_or_else(|| RpcError::invalid_params("missing edit".to_string()))
+                .and_then(|edit| {
+                    json::from_value::<WorkspaceEdit>(edit)
+                        .map_err(|err| RpcError::invalid_params(err.to_string()))
+                })?;
+            let response = match apply_workspace_edit_with(&client, &documents, &edit) {
+                Ok(notifications) => {
+                    handle.spawn(future::join_all(notifications)
+                        .map(|_| ())
+                        .map_err(|err| warn!("could not synchronize an applied edit: {:?}", err)));
+                    ObjectBuilder::new().insert("applied", true).build()
+                }
+                Err(err) => {
+                    ObjectBuilder::new()
+                        .insert("applied", false)
+                        .insert("failureReason", err.to_string())
+                        .build()
+                }
+            };
+            Ok(response)
+        });
+    }
+
+    /// Accepts the progress tokens created by the server with `window/workDoneProgress/create`.
+    /// The progress is then reported with `$/progress` notifications.
+    pub fn enable_work_done_progress(&self) {
+        self.warn_if_initialized();
+        self.client_capabilities.borrow_mut().work_done_progress(true);
+        self.on_request("window/workDoneProgress/create", |_| Ok(json::Value::Null));
+    }
+
+    /// Accepts the capabilities registered by the server after initialization, which are then
+    /// looked up like the ones it advertised.
+    pub fn enable_dynamic_registration(&self) {
+        self.warn_if_initialized();
+        self.client_capabilities.borrow_mut().dynamic_registration(true);
+
+        let capabilities = self.capabilities.clone();
+        self.on_request("client/registerCapability", move |params| {
+            if let Some(&json::Value::Array(ref registrations)) = params.find("registrations") {
+                for registration in registrations {
+                    let method = registration.find("method").and_then(|method| method.as_str()).unwrap_or("");
+                    let options = registration.find("registerOptions").cloned().unwrap_or(json::Value::Null);
+                    if !capabilities.register(method, options) {
+                        warn!("ignoring the registration of {}", method);
+                    }
+                }
+            }
+            Ok(json::Value::Null)
+        });
+
+        let capabilities = self.capabilities.clone();
+        self.on_request("client/unregisterCapability", move |params| {
+            // The protocol misspells this field.
+            if let Some(&json::Value::Array(ref unregistrations)) = params.find("unregisterations") {
+                for unregistration in unregistrations {
+                    if let Some(method) = unregistration.find("method").and_then(|method| method.as_str()) {
+                        capabilities.unregister(method);
+                    }
+                }
+            }
+            Ok(json::Value::Null)
+        });
+    }
+
+    /// The raw capabilities advertised by the server, or `None` before initialization.
+    pub fn server_capabilities(&self) -> Option<json::Value> {
+        self.capabilities.get()
+    }
+
+    /// Asks the server to execute a command. Fails with `Error::UnknownCommand` if the command is
+    /// not listed in the server's `executeCommandProvider` capability.
+    pub fn execute_command(&mut self, params: ExecuteCommandParams) -> impl 'static + Future<Item=Result<json::Value, ResponseError<()>>, Error=Error>
+    {
+        execute_command_with(self.client.clone(), &self.capabilities, params)
+    }
+
+    /// Register a handler for a command that is executed by the client rather than the server.
+    pub fn register_command<F>(&mut self, command: &str, handler: F)
+        where F: Fn(&[json::Value]) -> CustomResult<json::Value> + 'static
+    {
+        self.commands.borrow_mut().register(command, handler)
+    }
+
+    pub fn unregister_command(&mut self, command: &str) -> bool {
+        self.commands.borrow_mut().unregister(command)
+    }
+
+    /// Runs a `Command`, as returned in code actions or code lenses: client-side commands are
+    /// handled by their registered handler, the others are sent to the server.
+    pub fn run_command(&mut self, command: &languageserver_types::Command) -> impl 'static + Future<Item=Result<json::Value, ResponseError<()>>, Error=Error>
+    {
+        run_command_with(self.client.clone(), &self.capabilities, &self.commands, command)
+    }
+
+    /// The documents opened with `did_open_text_document`, as last synchronized with the server.
+    pub fn open_document(&self, uri: &str) -> Option<OpenDocument> {
+        self.documents.borrow().get(uri).cloned()
+    }
+
+    /// Requests code actions, restricted to the given kinds (see `code_action_kinds`). The
+    /// results are also filtered on the client, since servers may ignore `only`.
+    pub fn code_actions_of_kinds(&mut self, params: CodeActionParams, kinds: Vec<String>) -> impl 'static + Future<Item=Result<Vec<CodeActionOrCommand>, ResponseError<()>>, Error=Error>
+    {
+        let mut raw_params = json::to_value(&params);
+        if let Some(context) = raw_params.as_object_mut()
+            .and_then(|params| params.get_mut("context"))
+            .and_then(|context| context.as_object_mut()) {
+            context.insert("only".to_string(), json::to_value(&kinds));
+        }
+        self.call_with_params(REQUEST__CodeAction, raw_params)
+            .map(move |result| result.map(|actions| filter_by_kinds(actions, &kinds)))
+    }
 
-        self.client.call(RequestMessage::new(method.to_string(), json::to_value(params)))
-            .then(|res| handle_response(res?))
+    /// Fills in the `edit` of a code action that was returned without one. Actions are returned
+    /// unchanged if the server does not support resolving them.
+    pub fn resolve_code_action(&mut self, action: CodeAction) -> impl 'static + Future<Item=Result<CodeAction, ResponseError<()>>, Error=Error>
+    {
+        if self.capabilities.lookup("/codeActionProvider/resolveProvider") == Some(json::Value::Bool(true)) {
+            Either::A(self.call_with_params("codeAction/resolve", action))
+        } else {
+            Either::B(future::ok(Ok(action)))
+        }
+    }
+
+    /// Applies a code action: resolves it if needed, applies its edit to the affected documents,
+    /// then runs its command. Resolves to the result of the command, if any.
+    pub fn apply_code_action(&mut self, action: CodeActionOrCommand) -> impl 'static + Future<Item=Result<json::Value, ResponseError<()>>, Error=Error>
+    {
+        let action = match action {
+            CodeActionOrCommand::Command(command) => return Either::A(self.run_command(&command)),
+            CodeActionOrCommand::CodeAction(action) => action,
+        };
+
+        let resolved = if action.edit.is_none() && action.data.is_some() {
+            Either::A(self.resolve_code_action(action))
+        } else {
+            Either::B(future::ok(Ok(action)))
+        };
+
+        let client = self.client.clone();
+        let capabilities = self.capabilities.clone();
+        let commands = self.commands.clone();
+        let documents = self.documents.clone();
+
+        Either::B(resolved.and_then(move |resolved| -> Box<Future<Item=Result<json::Value, ResponseError<()>>, Error=Error>> {
+            let action = match resolved {
+                Ok(action) => action,
+                Err(err) => return Box::new(future::ok(Err(err))),
+            };
+            let notifications = match action.edit {
+                Some(ref edit) => {
+                    match apply_workspace_edit_with(&client, &documents, edit) {
+                        Ok(notifications) => notifications,
+                        Err(err) => return Box::new(future::err(err)),
+                    }
+                }
+                None => Vec::new(),
+            };
+            let command = action.command;
+            Box::new(future::join_all(notifications).and_then(move |_| {
+                match command {
+                    Some(command) => Either::A(run_command_with(client, &capabilities, &commands, &command)),
+                    None => Either::B(future::ok(Ok(json::Value::Null))),
+                }
+            }))
+        }))
+    }
+
+    /// Applies a `WorkspaceEdit`: open documents are edited and the server notified of the new
+    /// content, other files are edited on disk.
+    pub fn apply_workspace_edit(&self, edit: &WorkspaceEdit) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        future::result(apply_workspace_edit_with(&self.client, &self.documents, edit))
+            .and_then(future::join_all)
+            .map(|_| ())
     }
 
     requests!(
-        initialize: REQUEST__Initialize, InitializeParams, InitializeResult, InitializeError, "Initializes the server";
         shutdown: REQUEST__Shutdown, (), json::Value, (), "";
         completion: REQUEST__Completion, TextDocumentPositionParams, CompletionResult, (), "";
         resolve_completion: REQUEST__ResolveCompletionItem, CompletionItem, CompletionItem, (), "";
-        hover: REQUEST__Hover, TextDocumentPositionParams, Hover, (), "";
+        hover: REQUEST__Hover, TextDocumentPositionParams, Option<Hover>, (), "The hover at a position. Its contents can be rendered with `Hover::markup`.";
         signature_help: REQUEST__SignatureHelp, TextDocumentPositionParams, SignatureHelp, (), "";
+        signature_help_with_context: REQUEST__SignatureHelp, SignatureHelpParams, Option<SignatureHelp>, (), "Signature help telling the server how it was triggered, and what is showing. See `update_signature_help`.";
         goto_definition: REQUEST__GotoDefinition, TextDocumentPositionParams, LocationOrLocationList, (), "";
         find_references: REQUEST__References, ReferenceParams, Vec<Location>, (), "";
         document_highlights: REQUEST__DocumentHighlight, TextDocumentPositionParams, Vec<DocumentHighlight>, (), "";
-        document_symbols: REQUEST__DocumentSymbols, DocumentSymbolParams, Vec<SymbolInformation>, (), "";
+        document_symbols: REQUEST__DocumentSymbols, DocumentSymbolParams, DocumentSymbolResponse, (), "The symbols of a document, flat or nested depending on the server. See `outline` for a tree.";
         workspace_symbols: REQUEST__WorkspaceSymbols, WorkspaceSymbolParams, Vec<SymbolInformation>, (), "";
-        code_action: REQUEST__CodeAction, CodeActionParams, Vec<languageserver_types::Command>, (), "";
+        code_action: REQUEST__CodeAction, CodeActionParams, Vec<CodeActionOrCommand>, (), "";
         code_lens: REQUEST__CodeLens, CodeLensParams, Vec<CodeLens>, (), "";
         resolve_code_lens: REQUEST__CodeLensResolve, CodeLens, CodeLens, (), "";
         range_formatting: REQUEST__RangeFormatting, DocumentRangeFormattingParams, Vec<TextEdit>, (), "";
         on_type_formatting: REQUEST__OnTypeFormatting, DocumentRangeFormattingParams, Vec<TextEdit>, (), "";
         rename: REQUEST__Rename, RenameParams, WorkspaceEdit, (), "";
+        semantic_tokens_full: "textDocument/semanticTokens/full", SemanticTokensParams, Option<SemanticTokens>, (), "Raw semantic tokens for a whole document. See `semantic_tokens` for decoded tokens.";
+        semantic_tokens_full_delta: "textDocument/semanticTokens/full/delta", SemanticTokensDeltaParams, Option<SemanticTokensFullDeltaResult>, (), "Raw changes to the semantic tokens since a previous result.";
+        semantic_tokens_range: "textDocument/semanticTokens/range", SemanticTokensRangeParams, Option<SemanticTokens>, (), "Raw semantic tokens for a range of a document.";
+        resolve_inlay_hint: "inlayHint/resolve", InlayHint, InlayHint, (), "Fills in the tooltip, text edits or label locations of an inlay hint.";
+        prepare_call_hierarchy: "textDocument/prepareCallHierarchy", TextDocumentPositionParams, Option<Vec<CallHierarchyItem>>, (), "The call hierarchy items at a position. See `call_hierarchy` for a tree model.";
+        incoming_calls: "callHierarchy/incomingCalls", CallHierarchyIncomingCallsParams, Option<Vec<CallHierarchyIncomingCall>>, (), "The callers of a call hierarchy item.";
+        outgoing_calls: "callHierarchy/outgoingCalls", CallHierarchyOutgoingCallsParams, Option<Vec<CallHierarchyOutgoingCall>>, (), "The functions called by a call hierarchy item.";
+        prepare_type_hierarchy: "textDocument/prepareTypeHierarchy", TextDocumentPositionParams, Option<Vec<TypeHierarchyItem>>, (), "The type hierarchy items at a position.";
+        type_hierarchy_supertypes: "typeHierarchy/supertypes", TypeHierarchySupertypesParams, Option<Vec<TypeHierarchyItem>>, (), "The direct supertypes of a type hierarchy item.";
+        type_hierarchy_subtypes: "typeHierarchy/subtypes", TypeHierarchySubtypesParams, Option<Vec<TypeHierarchyItem>>, (), "The direct subtypes of a type hierarchy item.";
+        folding_range: "textDocument/foldingRange", FoldingRangeParams, Option<Vec<FoldingRange>>, (), "The raw folding ranges of a document. See `folding_model` for nested folds.";
+        selection_range: "textDocument/selectionRange", SelectionRangeParams, Option<Vec<SelectionRange>>, (), "The selection ranges around each of the given positions.";
+        prepare_rename: "textDocument/prepareRename", TextDocumentPositionParams, Option<PrepareRenameResponse>, (), "What would be renamed at a position. See `rename_target`.";
+        document_color: "textDocument/documentColor", DocumentColorParams, Option<Vec<ColorInformation>>, (), "The colors written in a document.";
+        color_presentation: "textDocument/colorPresentation", ColorPresentationParams, Option<Vec<ColorPresentation>>, (), "The ways a color can be written at a range.";
+        linked_editing_range: "textDocument/linkedEditingRange", TextDocumentPositionParams, Option<LinkedEditingRanges>, (), "The ranges linked to the one at a position. See `LinkedEditing`.";
+        document_diagnostic: "textDocument/diagnostic", DocumentDiagnosticParams, DocumentDiagnosticReport, (), "The diagnostics of a document, for servers that let them be pulled rather than publishing them.";
     );
 
+    /// Starts a completion session at a position. For open documents, the word being typed is
+    /// taken into account to filter the items.
+    pub fn start_completion(&mut self, params: TextDocumentPositionParams) -> impl 'static + Future<Item=Result<CompletionSession, ResponseError<()>>, Error=Error>
+    {
+        let (word_start, prefix) = match self.documents.borrow().get(&params.text_document.uri.to_string()) {
+            Some(document) => completion::word_before(&document.text, &params.position),
+            None => (params.position.clone(), String::new()),
+        };
+        let text_document = params.text_document.clone();
+        self.completion(params).map(move |result| {
+            result.map(|items| CompletionSession::new(text_document, word_start, prefix, items))
+        })
+    }
+
+    /// Requests the items of a session again, at its current cursor position. Only needed when
+    /// `needs_requery` says so.
+    pub fn requery_completion(&mut self, session: CompletionSession) -> impl 'static + Future<Item=Result<CompletionSession, ResponseError<()>>, Error=Error>
+    {
+        let params = TextDocumentPositionParams {
+            text_document: session.text_document().clone(),
+            position: session.cursor(),
+        };
+        self.completion(params).map(move |result| result.map(|items| session.with_result(items)))
+    }
+
+    /// Resolves the documentation and additional edits of a completion item, if the server
+    /// supports it. The item is returned unchanged otherwise.
+    pub fn resolve_completion_item(&mut self, item: CompletionItem) -> impl 'static + Future<Item=Result<CompletionItem, ResponseError<()>>, Error=Error>
+    {
+        if self.capabilities.lookup("/completionProvider/resolveProvider") == Some(json::Value::Bool(true)) {
+            Either::A(self.resolve_completion(item))
+        } else {
+            Either::B(future::ok(Ok(item)))
+        }
+    }
+
+    /// The code lenses of a document within the visible range, resolved. The lenses of open
+    /// documents are requested once per document version, and resolved only once; the cache is
+    /// dropped when the server asks for a refresh.
+    pub fn code_lenses(&mut self, text_document: TextDocumentIdentifier, visible: Range) -> impl 'static + Future<Item=Result<Vec<CodeLens>, ResponseError<()>>, Error=Error>
+    {
+        let uri = text_document.uri.to_string();
+        let version = self.documents.borrow().get(&uri).map(|document| document.version);
+        let cached = version.and_then(|version| self.code_lens_cache.borrow().get(&uri, version));
+
+        let lenses = match cached {
+            Some(lenses) => Either::A(future::ok(Ok(lenses))),
+            None => {
+                let cache = self.code_lens_cache.clone();
+                let uri = uri.clone();
+                Either::B(self.code_lens(CodeLensParams { text_document: text_document })
+                    .map(move |result| {
+                        result.map(|lenses| {
+                            if let Some(version) = version {
+                                cache.borrow_mut().insert(uri, version, lenses.clone());
+                            }
+                            lenses
+                        })
+                    }))
+            }
+        };
+
+        let can_resolve = self.capabilities.lookup("/codeLensProvider/resolveProvider") == Some(json::Value::Bool(true));
+        let mut client = self.client.clone();
+        let cache = self.code_lens_cache.clone();
+
+        lenses.and_then(move |result| {
+            let lenses = match result {
+                Ok(lenses) => lenses,
+                Err(err) => return Either::A(future::ok(Err(err))),
+            };
+            let resolutions: Vec<_> = code_lens::visible_lenses(lenses, &visible, can_resolve)
+                .into_iter()
+                .map(|(index, lens, needs_resolving)| {
+                    if !needs_resolving {
+                        return Either::A(future::ok((index, lens)));
+                    }
+                    let unresolved = lens.clone();
+                    Either::B(call_with_params::<_, CodeLens, ()>(&mut client, REQUEST__CodeLensResolve, lens)
+                        .map(move |result| {
+                            match result {
+                                Ok(resolved) => (index, resolved),
+                                Err(err) => {
+                                    warn!("could not resolve code lens: {:?}", err);
+                                    (index, unresolved)
+                                }
+                            }
+                        }))
+                })
+                .collect();
+
+            Either::B(future::join_all(resolutions).map(move |resolved| {
+                let mut visible_lenses = Vec::with_capacity(resolved.len());
+                for (index, lens) in resolved {
+                    if let Some(version) = version {
+                        cache.borrow_mut().set_resolved(&uri, version, index, lens.clone());
+                    }
+                    visible_lenses.push(lens);
+                }
+                Ok(visible_lenses)
+            }))
+        })
+    }
+
+    /// Starts tracking the signature help of a document, with the trigger and retrigger
+    /// characters of the server. Use `update_signature_help` as the user types.
+    pub fn signature_help_session(&self, text_document: TextDocumentIdentifier) -> Rc<RefCell<SignatureHelpSession>> {
+        let characters = |pointer: &str| {
+            self.capabilities
+                .lookup(pointer)
+                .and_then(|characters| json::from_value::<Vec<String>>(characters).ok())
+                .unwrap_or_default()
+        };
+        Rc::new(RefCell::new(SignatureHelpSession::new(text_document,
+                                                       characters("/signatureHelpProvider/triggerCharacters"),
+                                                       characters("/signatureHelpProvider/retriggerCharacters"))))
+    }
+
+    /// Updates a signature help session after the user typed `typed` at `position`, or asked
+    /// for signature help if `typed` is `None`. The server is not queried if the typed text
+    /// neither triggers signature help nor edits a call whose signature help is showing.
+    /// Resolves to the signature help now showing.
+    pub fn update_signature_help(&mut self, session: &Rc<RefCell<SignatureHelpSession>>, position: Position, typed: Option<&str>) -> impl 'static + Future<Item=Result<Option<SignatureHelp>, ResponseError<()>>, Error=Error>
+    {
+        let context = match typed {
+            Some(typed) => session.borrow().typed_context(typed),
+            None => Some(session.borrow().invoked_context()),
+        };
+        let context = match context {
+            Some(context) => context,
+            None => return Either::A(future::ok(Ok(session.borrow().help().cloned()))),
+        };
+
+        let params = SignatureHelpParams {
+            text_document: session.borrow().text_document().clone(),
+            position: position,
+            context: Some(context),
+        };
+        let session = session.clone();
+        Either::B(self.signature_help_with_context(params).map(move |result| {
+            result.map(|help| {
+                let mut session = session.borrow_mut();
+                session.update(help);
+                session.help().cloned()
+            })
+        }))
+    }
+
+    /// Finds what would be renamed at a position, before asking the user for a new name.
+    /// Resolves to `None` if there is nothing to rename there. Servers without `prepareRename`
+    /// support are assumed to rename the word under the cursor, read from disk for documents
+    /// that are not open.
+    pub fn rename_target(&mut self, params: TextDocumentPositionParams) -> impl 'static + Future<Item=Result<Option<RenameTarget>, ResponseError<()>>, Error=Error>
+    {
+        let uri = params.text_document.uri.to_string();
+        let text = self.documents
+            .borrow()
+            .get(&uri)
+            .map(|document| document.text.clone());
+        let text = text.or_else(|| documents::read_from_disk(&uri).ok());
+        let position = params.position.clone();
+
+        let response = if self.capabilities.lookup("/renameProvider/prepareProvider") == Some(json::Value::Bool(true)) {
+            Either::A(self.prepare_rename(params))
+        } else {
+            Either::B(future::ok(Ok(Some(PrepareRenameResponse::DefaultBehavior))))
+        };
+        response.map(move |result| {
+            result.map(|response| rename::rename_target(response, text.as_ref().map(|text| text.as_str()), &position))
+        })
+    }
+
+    /// The changes an edit, like the one returned by `rename`, would make to each file, as
+    /// unified diffs. Nothing is applied.
+    pub fn preview_workspace_edit(&self, edit: &WorkspaceEdit) -> CustomResult<Vec<FileDiff>> {
+        rename::preview(&self.documents.borrow(), edit)
+    }
+
+    /// The symbols of a document as an outline tree, whether the server returns them nested or
+    /// flat.
+    pub fn outline(&mut self, params: DocumentSymbolParams) -> impl 'static + Future<Item=Result<OutlineTree, ResponseError<()>>, Error=Error>
+    {
+        self.document_symbols(params).map(|result| result.map(OutlineTree::new))
+    }
+
+    /// The folding ranges of a document, nested and de-duplicated.
+    pub fn folding_model(&mut self, text_document: TextDocumentIdentifier) -> impl 'static + Future<Item=Result<FoldingModel, ResponseError<()>>, Error=Error>
+    {
+        self.folding_range(FoldingRangeParams { text_document: text_document })
+            .map(|result| result.map(|ranges| FoldingModel::new(ranges.unwrap_or_default())))
+    }
+
+    /// A walker over the selection ranges around a cursor position.
+    pub fn selection_walker(&mut self, text_document: TextDocumentIdentifier, position: Position) -> impl 'static + Future<Item=Result<Option<SelectionWalker>, ResponseError<()>>, Error=Error>
+    {
+        let params = SelectionRangeParams {
+            text_document: text_document,
+            positions: vec![position],
+        };
+        self.selection_range(params).map(|result| {
+            result.map(|selections| {
+                selections.and_then(|selections| selections.into_iter().next())
+                    .map(SelectionWalker::new)
+            })
+        })
+    }
+
+    /// Collects the inheritance graph around a type, following supertypes and/or subtypes at
+    /// most `max_depth` levels away from it.
+    pub fn type_hierarchy_graph(&mut self, item: TypeHierarchyItem, direction: TypeHierarchyDirection, max_depth: usize) -> impl 'static + Future<Item=Result<TypeGraph, ResponseError<()>>, Error=Error>
+    {
+        type_hierarchy::walk(self.client.clone(), item, direction, max_depth)
+    }
+
+    /// Prepares a call hierarchy tree, whose roots are the items at the given position. Nodes
+    /// are then expanded with `expand_call_hierarchy`.
+    pub fn call_hierarchy(&mut self, params: TextDocumentPositionParams, direction: CallDirection) -> impl 'static + Future<Item=Result<Rc<RefCell<CallHierarchyTree>>, ResponseError<()>>, Error=Error>
+    {
+        self.prepare_call_hierarchy(params).map(move |result| {
+            result.map(|items| {
+                Rc::new(RefCell::new(CallHierarchyTree::new(direction, items.unwrap_or_default())))
+            })
+        })
+    }
+
+    /// Expands a node of a call hierarchy tree, and resolves to its children. The server is only
+    /// queried if the calls of the node's item are not already known.
+    pub fn expand_call_hierarchy(&mut self, tree: &Rc<RefCell<CallHierarchyTree>>, node: NodeId) -> impl 'static + Future<Item=Result<Vec<NodeId>, ResponseError<()>>, Error=Error>
+    {
+        let cached = tree.borrow_mut().expand_cached(node).map(|children| children.to_vec());
+        if let Some(children) = cached {
+            return Either::A(future::ok(Ok(children)));
+        }
+
+        let (direction, item) = {
+            let tree = tree.borrow();
+            (tree.direction(), tree.node(node).item.clone())
+        };
+        let calls = match direction {
+            CallDirection::Incoming => {
+                Either::A(self.incoming_calls(CallHierarchyIncomingCallsParams { item: item })
+                    .map(|result| {
+                        result.map(|calls| {
+                            calls.unwrap_or_default()
+                                .into_iter()
+                                .map(|call| (call.from, call.from_ranges))
+                                .collect::<Vec<_>>()
+                        })
+                    }))
+            }
+            CallDirection::Outgoing => {
+                Either::B(self.outgoing_calls(CallHierarchyOutgoingCallsParams { item: item })
+                    .map(|result| {
+                        result.map(|calls| {
+                            calls.unwrap_or_default()
+                                .into_iter()
+                                .map(|call| (call.to, call.from_ranges))
+                                .collect::<Vec<_>>()
+                        })
+                    }))
+            }
+        };
+
+        let tree = tree.clone();
+        Either::B(calls.map(move |result| {
+            result.map(|calls| tree.borrow_mut().expand_with(node, calls).to_vec())
+        }))
+    }
+
+    /// The inlay hints in a range of a document. For open documents, hints are cached by
+    /// document version and range, and only requested again when the document changes, when the
+    /// range goes beyond those already requested, or when the server asks for a refresh.
+    pub fn inlay_hint(&mut self, params: InlayHintParams) -> impl 'static + Future<Item=Result<Vec<InlayHint>, ResponseError<()>>, Error=Error>
+    {
+        let uri = params.text_document.uri.to_string();
+        let version = self.documents.borrow().get(&uri).map(|document| document.version);
+        if let Some(version) = version {
+            if let Some(hints) = self.inlay_hint_cache.borrow().get(&uri, version, &params.range) {
+                return Either::A(future::ok(Ok(hints)));
+            }
+        }
+
+        let cache = self.inlay_hint_cache.clone();
+        let range = params.range.clone();
+        Either::B(self.call_with_params::<_, Option<Vec<InlayHint>>, ()>("textDocument/inlayHint", params)
+            .map(move |result| {
+                result.map(|hints| {
+                    let hints = hints.unwrap_or_default();
+                    if let Some(version) = version {
+                        cache.borrow_mut().insert(uri, version, range, hints.clone());
+                    }
+                    hints
+                })
+            }))
+    }
+
+    /// The legend used to decode semantic tokens, from the server capabilities.
+    pub fn semantic_tokens_legend(&self) -> Option<SemanticTokensLegend> {
+        self.capabilities
+            .lookup("/semanticTokensProvider/legend")
+            .and_then(|legend| json::from_value(legend).ok())
+    }
+
+    /// Decode raw tokens, as returned by `semantic_tokens_range`, with the server's legend.
+    pub fn decode_semantic_tokens(&self, tokens: &SemanticTokens) -> CustomResult<Vec<SemanticToken>> {
+        self.semantic_tokens_legend()
+            .map(|legend| semantic_tokens::decode(&tokens.data, &legend))
+            .ok_or(Error::Unsupported("semanticTokensProvider"))
+    }
+
+    /// The decoded semantic tokens of a whole document. After the first call for a document, only
+    /// a delta is requested if the server supports it, and applied to the previous result. If
+    /// the delta request fails, the previous result is dropped and all the tokens requested again.
+    pub fn semantic_tokens(&mut self, text_document: TextDocumentIdentifier) -> impl 'static + Future<Item=Result<Vec<SemanticToken>, ResponseError<()>>, Error=Error>
+    {
+        let legend = match self.semantic_tokens_legend() {
+            Some(legend) => legend,
+            None => return Either::A(future::err(Error::Unsupported("semanticTokensProvider"))),
+        };
+        let uri = text_document.uri.to_string();
+        let previous = self.semantic_tokens_cache.borrow().get(&uri).cloned();
+        let supports_delta = self.capabilities.is_enabled("/semanticTokensProvider/full/delta");
+
+        let response = match previous {
+            Some(SemanticTokens { result_id: Some(ref result_id), .. }) if supports_delta => {
+                let mut client = self.client.clone();
+                let cache = self.semantic_tokens_cache.clone();
+                let uri = uri.clone();
+                let full_params = SemanticTokensParams { text_document: text_document.clone() };
+                let delta = self.semantic_tokens_full_delta(SemanticTokensDeltaParams {
+                    text_document: text_document,
+                    previous_result_id: result_id.clone(),
+                });
+                Either::A(delta.and_then(move |result| {
+                    match result {
+                        Ok(tokens) => Either::A(future::ok(Ok(tokens))),
+                        Err(err) => {
+                            warn!("semantic tokens delta failed, requesting them all: {:?}", err);
+                            cache.borrow_mut().remove(&uri);
+                            let full = call_with_params(&mut client, "textDocument/semanticTokens/full", full_params);
+                            Either::B(full.map(|result: Result<Option<SemanticTokens>, ResponseError<()>>| {
+                                result.map(|tokens| tokens.map(SemanticTokensFullDeltaResult::Tokens))
+                            }))
+                        }
+                    }
+                }))
+            }
+            _ => {
+                Either::B(self.semantic_tokens_full(SemanticTokensParams { text_document: text_document })
+                    .map(|result| result.map(|tokens| tokens.map(SemanticTokensFullDeltaResult::Tokens))))
+            }
+        };
+
+        let cache = self.semantic_tokens_cache.clone();
+        Either::B(response.map(move |result| {
+            result.map(|tokens| {
+                let current = match tokens {
+                    Some(SemanticTokensFullDeltaResult::Tokens(tokens)) => Some(tokens),
+                    Some(SemanticTokensFullDeltaResult::Delta(delta)) => {
+                        previous.map(|previous| SemanticTokens {
+                            result_id: delta.result_id,
+                            data: semantic_tokens::apply_edits(&previous.data, &delta.edits),
+                        })
+                    }
+                    None => None,
+                };
+                let decoded = current.as_ref()
+                    .map_or(Vec::new(), |tokens| semantic_tokens::decode(&tokens.data, &legend));
+                match current {
+                    Some(tokens) => cache.borrow_mut().insert(uri, tokens),
+                    None => cache.borrow_mut().remove(&uri),
+                };
+                decoded
+            })
+        }))
+    }
+
     // TODO: DocumentLink
 
     fn notify_with_params<'a, REQ>(&self, method: &'static str, params: REQ) -> impl 'a + Future<Item=(), Error=Error>
@@ -178,11 +1187,362 @@ impl LanguageServer {
     client_notifications!(
         cancel_request: NOTIFICATION__Cancel, CancelParams, "";
         did_change_configuration: NOTIFICATION__WorkspaceChangeConfiguration, DidChangeConfigurationParams, "";
-        did_change_text_document: NOTIFICATION__DidChangeTextDocument, DidChangeTextDocumentParams, "";
         did_change_watched_files: NOTIFICATION__DidChangeWatchedFiles, DidChangeWatchedFilesParams, "";
-        did_close_text_document: NOTIFICATION__DidCloseTextDocument, DidCloseTextDocumentParams, "";
-        did_open_text_document: NOTIFICATION__DidOpenTextDocument, DidOpenTextDocumentParams, "";
         did_save_text_document: NOTIFICATION__DidSaveTextDocument, DidSaveTextDocumentParams, "";
         exit: NOTIFICATION__Exit, (), "";
     );
+
+    /// Tells the server that the initialize result was received, after which it may send
+    /// requests of its own.
+    pub fn initialized(&self) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        self.notify_with_params("initialized", ObjectBuilder::new().build())
+    }
+
+    /// The language id of a file, as declared by the `Language` of the server.
+    pub fn language_id(&self, path: &Path) -> Option<String> {
+        self.language.language_id(path)
+    }
+
+    /// Opens a file on the server, with its content on disk and the language id declared by
+    /// the `Language` of the server.
+    pub fn open_file(&self, path: &Path) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        let params = self.language_id(path)
+            .ok_or_else(|| Error::UnknownLanguage(path.to_string_lossy().into_owned()))
+            .and_then(|language_id| {
+                let uri = url::Url::from_file_path(path)
+                    .map_err(|_| Error::InvalidUri(path.to_string_lossy().into_owned()))?;
+                let text = documents::read_from_disk(uri.as_str())?;
+                let params = ObjectBuilder::new()
+                    .insert_object("textDocument", |document| {
+                        document.insert("uri", uri.as_str())
+                            .insert("languageId", language_id)
+                            .insert("version", 0)
+                            .insert("text", text)
+                    })
+                    .build();
+                Ok(json::from_value::<DidOpenTextDocumentParams>(params)?)
+            });
+        match params {
+            Ok(params) => Either::A(self.did_open_text_document(params)),
+            Err(err) => Either::B(future::err(err)),
+        }
+    }
+
+    /// Opens a document on the server and starts tracking its content.
+    pub fn did_open_text_document(&self, params: DidOpenTextDocumentParams) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        self.documents.borrow_mut().open(OpenDocument {
+            uri: params.text_document.uri.to_string(),
+            language_id: params.text_document.language_id.clone(),
+            version: params.text_document.version as u64,
+            text: params.text_document.text.clone(),
+        });
+        self.notify_with_params(NOTIFICATION__DidOpenTextDocument, params)
+    }
+
+    pub fn did_change_text_document(&self, params: DidChangeTextDocumentParams) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        self.documents.borrow_mut().change(&params.text_document.uri.to_string(),
+                                           params.text_document.version as u64,
+                                           &params.content_changes);
+        self.notify_with_params(NOTIFICATION__DidChangeTextDocument, params)
+    }
+
+    pub fn did_close_text_document(&self, params: DidCloseTextDocumentParams) -> impl 'static + Future<Item=(), Error=Error>
+    {
+        let uri = params.text_document.uri.to_string();
+        self.documents.borrow_mut().close(&uri);
+        self.semantic_tokens_cache.borrow_mut().remove(&uri);
+        self.inlay_hint_cache.borrow_mut().invalidate(&uri);
+        self.code_lens_cache.borrow_mut().invalidate(&uri);
+        self.notify_with_params(NOTIFICATION__DidCloseTextDocument, params)
+    }
+}
+
+/// Answers a refresh request of the server by dropping the cached results, and telling the user
+/// of the client with `notification` so that they are requested again.
+fn register_refresh<F>(server_requests: &RefCell<ServerRequestHandlers>,
+                       method: &str,
+                       notifications: mio::channel::Sender<ServerNotification>,
+                       notification: ServerNotification,
+                       clear: F)
+    where F: Fn() + 'static
+{
+    server_requests.borrow_mut().register(method, move |_| {
+        clear();
+        notifications.send(notification.clone())
+            .map_err(|_| RpcError::new(-32603, "client is shutting down".to_string()))?;
+        Ok(json::Value::Null)
+    });
+}
+
+fn execute_command_with(mut client: RpcClient, capabilities: &Capabilities, params: ExecuteCommandParams) -> impl 'static + Future<Item=Result<json::Value, ResponseError<()>>, Error=Error>
+{
+    if capabilities.supports_command(&params.command) {
+        Either::A(call_with_params(&mut client, "workspace/executeCommand", params))
+    } else {
+        Either::B(future::err(Error::UnknownCommand(params.command)))
+    }
+}
+
+fn run_command_with(client: RpcClient, capabilities: &Capabilities, commands: &Rc<RefCell<CommandRegistry>>, command: &languageserver_types::Command) -> impl 'static + Future<Item=Result<json::Value, ResponseError<()>>, Error=Error>
+{
+    let arguments = command.arguments.clone().unwrap_or_default();
+    let local_result = commands.borrow().run(&command.command, &arguments);
+    match local_result {
+        Some(result) => Either::A(future::result(result.map(Ok))),
+        None => Either::B(execute_command_with(client, capabilities, ExecuteCommandParams {
+            command: command.command.clone(),
+            arguments: command.arguments.clone(),
+        })),
+    }
+}
+
+/// Applies the edit, and returns the `didChange` notifications to send for open documents.
+/// The files that are not open are all read before any of them is written, and open documents
+/// are only edited once they are, so that an edit failing is not partially applied.
+fn apply_workspace_edit_with(client: &RpcClient, documents: &Rc<RefCell<Documents>>, edit: &WorkspaceEdit) -> CustomResult<Vec<MessageHandle>>
+{
+    let mut open = Vec::new();
+    let mut on_disk = Vec::new();
+    for (uri, edits) in documents::workspace_edit_changes(edit)? {
+        let is_open = documents.borrow().get(&uri).is_some();
+        if is_open {
+            open.push((uri, edits));
+        } else {
+            let text = documents::read_from_disk(&uri)?;
+            on_disk.push((uri, documents::apply_text_edits(&text, &edits)));
+        }
+    }
+    for (uri, text) in on_disk {
+        documents::write_to_disk(&uri, &text)?;
+    }
+
+    let mut notifications = Vec::new();
+    for (uri, edits) in open {
+        let applied = documents.borrow_mut().apply_edits(&uri, &edits);
+        if let Some((version, text)) = applied {
+            let params = documents::full_text_change(&uri, version, &text);
+            notifications.push(client.notify(Notification::new(NOTIFICATION__DidChangeTextDocument.to_string(), params)));
+        }
+    }
+    Ok(notifications)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    struct Cat;
+
+    impl Language for Cat {
+        fn get_command(&self) -> Vec<String> {
+            vec!["cat".to_string()]
+        }
+
+        fn root_markers(&self) -> Vec<String> {
+            vec!["Cargo.toml".to_string()]
+        }
+
+        fn language_ids(&self) -> Vec<(String, String)> {
+            vec![("rs".to_string(), "rust".to_string())]
+        }
+    }
+
+    fn project() -> PathBuf {
+        let project = env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(project.join("src")).unwrap();
+        File::create(project.join("Cargo.toml")).unwrap();
+        project
+    }
+
+    #[test]
+    fn servers_handle_the_files_of_their_project() {
+        let core = Core::new().unwrap();
+        let project = project();
+        let server = LanguageServer::for_file(Cat, &project.join("src").join("main.rs"), core.handle()).unwrap();
+        assert_eq!(server.root(), Some(project.as_path()));
+
+        assert!(server.can_handle(&project.join("src").join("lib.rs")));
+        assert!(!server.can_handle(&project.join("README.md")));
+        assert!(!server.can_handle(&env::temp_dir().join(Uuid::new_v4().to_string()).join("main.rs")));
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn relative_files_are_found_from_the_current_directory() {
+        let core = Core::new().unwrap();
+        let server = LanguageServer::for_file(Cat, Path::new("main.rs"), core.handle()).unwrap();
+        let current = env::current_dir().unwrap();
+        assert_eq!(server.root(), Some(current.as_path()));
+        assert!(server.can_handle(&current.join("main.rs")));
+    }
+
+    #[test]
+    fn initialize_root_points_the_server_to_its_project() {
+        let core = Core::new().unwrap();
+        let project = project();
+        let mut server = LanguageServer::for_file(Cat, &project.join("src").join("main.rs"), core.handle()).unwrap();
+
+        drop(server.initialize_root(None));
+        let uri = json::to_value(roots::root_uri(&project));
+        let params = server.initialize_params.clone().unwrap();
+        assert_eq!(params.pointer("/rootUri"), Some(&uri));
+        assert_eq!(params.pointer("/workspaceFolders/0/uri"), Some(&uri));
+        assert_eq!(server.workspace_folders(), vec![project.clone()]);
+
+        let nested = project.join("src");
+        drop(server.initialize_root(Some(&nested)));
+        let params = server.initialize_params.clone().unwrap();
+        assert_eq!(params.pointer("/rootUri"), Some(&json::to_value(roots::root_uri(&nested))));
+        assert_eq!(server.workspace_folders(), vec![nested]);
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn initialize_params_set_the_workspace_folders() {
+        let core = Core::new().unwrap();
+        let project = project();
+        let mut server = LanguageServer::for_file(Cat, &project.join("src").join("main.rs"), core.handle()).unwrap();
+
+        drop(server.initialize_with(json::from_str(r#"{"rootUri": "file:///a/"}"#).unwrap()));
+        assert_eq!(server.workspace_folders(), vec![PathBuf::from("/a")]);
+        drop(server.initialize_with(json::from_str(r#"{"workspaceFolders": [{"uri": "file:///b", "name": "b"},
+                                                                            {"uri": "file:///c", "name": "c"}]}"#)
+            .unwrap()));
+        assert_eq!(server.workspace_folders(), vec![PathBuf::from("/b"), PathBuf::from("/c")]);
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn workspace_folders_are_changed_and_answered_to_the_server() {
+        let mut core = Core::new().unwrap();
+        let project = project();
+        let server = LanguageServer::for_file(Cat, &project.join("src").join("main.rs"), core.handle()).unwrap();
+        let other = env::temp_dir().join(Uuid::new_v4().to_string());
+
+        match core.run(server.add_workspace_folder(&other)) {
+            Err(Error::Unsupported(_)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(server.workspace_folders(), vec![project.clone()]);
+
+        server.capabilities.set(Some(json::from_str(r#"{
+            "workspace": {"workspaceFolders": {"supported": true, "changeNotifications": true}}
+        }"#).unwrap()));
+        core.run(server.add_workspace_folder(&other)).unwrap();
+        core.run(server.add_workspace_folder(&other)).unwrap();
+        assert_eq!(server.workspace_folders(), vec![project.clone(), other.clone()]);
+        assert!(server.can_handle(&other.join("main.rs")));
+
+        let request = messages::ServerRequest {
+            jsonrpc: "2.0".to_string(),
+            id: json::to_value(1),
+            method: "workspace/workspaceFolders".to_string(),
+            params: None,
+        };
+        let folders = ServerRequestHandlers::handle(&server.server_requests, &request).unwrap();
+        assert_eq!(folders.pointer("/1/uri"), Some(&json::to_value(roots::root_uri(&other))));
+
+        core.run(server.remove_workspace_folder(&project)).unwrap();
+        assert_eq!(server.workspace_folders(), vec![other.clone()]);
+        let folders = ServerRequestHandlers::handle(&server.server_requests, &request).unwrap();
+        assert_eq!(folders.as_array().map(Vec::len), Some(1));
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    struct Hooked {
+        directory: PathBuf,
+    }
+
+    impl Language for Hooked {
+        fn get_command(&self) -> Vec<String> {
+            vec!["sh".to_string(), "-c".to_string(), "echo \"$GREETING\" > hooks.txt".to_string()]
+        }
+
+        fn env(&self) -> Vec<(String, String)> {
+            vec![("GREETING".to_string(), "hello".to_string())]
+        }
+
+        fn working_dir(&self) -> Option<PathBuf> {
+            Some(self.directory.clone())
+        }
+
+        fn initialization_options(&self) -> Option<json::Value> {
+            Some(ObjectBuilder::new().insert("checkOnSave", true).build())
+        }
+
+        fn client_capabilities(&self, defaults: json::Value) -> json::Value {
+            let mut capabilities = defaults;
+            if let Some(capabilities) = capabilities.as_object_mut() {
+                capabilities.insert("experimental".to_string(), ObjectBuilder::new().insert("hooked", true).build());
+            }
+            capabilities
+        }
+    }
+
+    #[test]
+    fn servers_are_spawned_with_the_environment_and_directory_of_their_language() {
+        let core = Core::new().unwrap();
+        let project = project();
+        let server = LanguageServer::new(Hooked { directory: project.clone() }, core.handle()).unwrap();
+        server.process.borrow_mut().wait().unwrap();
+
+        let mut written = String::new();
+        File::open(project.join("hooks.txt")).unwrap().read_to_string(&mut written).unwrap();
+        assert_eq!(written, "hello\n");
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn initialize_params_are_completed_by_the_language() {
+        let core = Core::new().unwrap();
+        let project = project();
+        let mut server = LanguageServer::new(Hooked { directory: project.clone() }, core.handle()).unwrap();
+
+        drop(server.initialize_with(ObjectBuilder::new().build()));
+        let params = server.initialize_params.clone().unwrap();
+        assert_eq!(params.pointer("/initializationOptions/checkOnSave"), Some(&json::Value::Bool(true)));
+        assert_eq!(params.pointer("/capabilities/experimental/hooked"), Some(&json::Value::Bool(true)));
+        assert_eq!(params.pointer("/capabilities/workspace/configuration"), Some(&json::Value::Bool(true)));
+
+        // The params given by the caller win.
+        drop(server.initialize_with(json::from_str(r#"{"initializationOptions": {"checkOnSave": false},
+                                                        "capabilities": {}}"#)
+            .unwrap()));
+        let params = server.initialize_params.clone().unwrap();
+        assert_eq!(params.pointer("/initializationOptions/checkOnSave"), Some(&json::Value::Bool(false)));
+        assert_eq!(params.pointer("/capabilities/experimental"), None);
+
+        fs::remove_dir_all(&project).unwrap();
+    }
+
+    #[test]
+    fn rename_targets_of_documents_not_open_are_read_from_disk() {
+        let mut core = Core::new().unwrap();
+        let project = project();
+        let file = project.join("src").join("main.rs");
+        File::create(&file).unwrap().write_all(b"let foo = 1;\n").unwrap();
+        let mut server = LanguageServer::for_file(Cat, &file, core.handle()).unwrap();
+
+        let params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: url::Url::from_file_path(&file).unwrap() },
+            position: Position { line: 0, character: 5 },
+        };
+        let target = core.run(server.rename_target(params)).unwrap().unwrap().unwrap();
+        assert_eq!(target.placeholder, "foo");
+
+        fs::remove_dir_all(&project).unwrap();
+    }
 }
@@ -0,0 +1,126 @@
+//! Linked editing ranges: ranges that always have the same content, like the names of an
+//! opening and a closing HTML tag.
+use documents::{offset_at, range_contains_range};
+use languageserver_types::{Range, TextEdit};
+use regex::Regex;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkedEditingRanges {
+    pub ranges: Vec<Range>,
+    /// The pattern the content of the ranges must match for them to stay linked.
+    #[serde(rename = "wordPattern", skip_serializing_if = "Option::is_none")]
+    pub word_pattern: Option<String>,
+}
+
+/// Linked ranges, with their word pattern compiled once to check the edits made in them.
+#[derive(Clone, Debug)]
+pub struct LinkedEditing {
+    linked: LinkedEditingRanges,
+    word_regex: Option<Regex>,
+}
+
+impl LinkedEditing {
+    /// An invalid word pattern is ignored, with a warning.
+    pub fn new(linked: LinkedEditingRanges) -> Self {
+        let word_regex = linked.word_pattern.as_ref().and_then(|pattern| {
+            match Regex::new(&format!("^(?:{})$", pattern)) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    warn!("ignoring invalid word pattern {:?}: {}", pattern, err);
+                    None
+                }
+            }
+        });
+        LinkedEditing {
+            linked: linked,
+            word_regex: word_regex,
+        }
+    }
+
+    pub fn ranges(&self) -> &[Range] {
+        &self.linked.ranges
+    }
+
+    /// The edits mirroring, in the other linked ranges, an edit made inside one of them. Returns
+    /// `None` when the edit is not inside a linked range, or when the edited content no longer
+    /// matches the word pattern, in which case the ranges should no longer be considered linked.
+    /// All the edits are relative to `text`, the content before the edit.
+    pub fn mirrored_edits(&self, text: &str, edit: &TextEdit) -> Option<Vec<TextEdit>> {
+        let ranges = &self.linked.ranges;
+        let edited = match ranges.iter().position(|range| range_contains_range(range, &edit.range)) {
+            Some(index) => index,
+            None => return None,
+        };
+
+        let range = &ranges[edited];
+        let (start, end) = (offset_at(text, &range.start), offset_at(text, &range.end));
+        let (edit_start, edit_end) = (offset_at(text, &edit.range.start), offset_at(text, &edit.range.end));
+        let content = format!("{}{}{}", &text[start..edit_start], edit.new_text, &text[edit_end..end]);
+
+        if let Some(ref regex) = self.word_regex {
+            if !content.is_empty() && !regex.is_match(&content) {
+                return None;
+            }
+        }
+
+        Some(ranges.iter()
+            .enumerate()
+            .filter(|&(index, _)| index != edited)
+            .map(|(_, range)| {
+                TextEdit {
+                    range: range.clone(),
+                    new_text: content.clone(),
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use documents::apply_text_edits;
+    use languageserver_types::{Position, Range, TextEdit};
+    use serde_json as json;
+
+    fn tags(word_pattern: &str) -> LinkedEditing {
+        LinkedEditing::new(json::from_str(&format!(r#"{{"ranges": [
+            {{"start": {{"line": 0, "character": 1}}, "end": {{"line": 0, "character": 4}}}},
+            {{"start": {{"line": 0, "character": 11}}, "end": {{"line": 0, "character": 14}}}}
+        ], "wordPattern": "{}"}}"#, word_pattern))
+            .unwrap())
+    }
+
+    fn edit(start: u64, end: u64, new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range {
+                start: Position { line: 0, character: start },
+                end: Position { line: 0, character: end },
+            },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn edits_are_mirrored_in_linked_ranges() {
+        let text = "<div>text</div>";
+        let typed = edit(4, 4, "-x");
+        let mut edits = tags("[a-z-]+").mirrored_edits(text, &typed).unwrap();
+        edits.push(typed);
+        assert_eq!(apply_text_edits(text, &edits), "<div-x>text</div-x>");
+    }
+
+    #[test]
+    fn edits_breaking_the_word_pattern_are_not_mirrored() {
+        let tags = tags("[a-z-]+");
+        assert!(tags.mirrored_edits("<div>text</div>", &edit(4, 4, " ")).is_none());
+        assert!(tags.mirrored_edits("<div>text</div>", &edit(6, 6, "x")).is_none());
+    }
+
+    #[test]
+    fn invalid_word_patterns_are_ignored() {
+        let edits = tags("[a-z").mirrored_edits("<div>text</div>", &edit(4, 4, " ")).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "div ");
+    }
+}
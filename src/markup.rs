@@ -0,0 +1,696 @@
+//! Rendering of the documentation returned by servers, in hovers, completion items and
+//! signature help: markdown is rendered for terminals, as plain text or with ANSI styles.
+use languageserver_types::{CompletionItem, Range};
+use serde_json as json;
+
+const RESET: &'static str = "\x1b[0m";
+const BOLD: &'static str = "\x1b[1m";
+const HEADING: &'static str = "\x1b[1;4m";
+const DIM: &'static str = "\x1b[2m";
+const ITALIC: &'static str = "\x1b[3m";
+const UNDERLINE: &'static str = "\x1b[4m";
+const CODE: &'static str = "\x1b[36m";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Hover {
+    /// A `MarkedString`, an array of them, or a `MarkupContent`. See `markup`.
+    pub contents: json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+}
+
+impl Hover {
+    pub fn markup(&self) -> Markup {
+        Markup::from_json(&self.contents)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MarkupFormat {
+    PlainText,
+    Markdown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderStyle {
+    Plain,
+    Ansi,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Markup {
+    pub format: MarkupFormat,
+    pub value: String,
+}
+
+impl Markup {
+    pub fn markdown(value: String) -> Self {
+        Markup {
+            format: MarkupFormat::Markdown,
+            value: value,
+        }
+    }
+
+    pub fn plain_text(value: String) -> Self {
+        Markup {
+            format: MarkupFormat::PlainText,
+            value: value,
+        }
+    }
+
+    /// Normalizes any of the forms documentation takes in the protocol: a string, a
+    /// `{language, value}` marked string, a `{kind, value}` markup content, or an array of
+    /// marked strings.
+    pub fn from_json(value: &json::Value) -> Self {
+        match *value {
+            json::Value::String(ref value) => Markup::markdown(value.clone()),
+            json::Value::Array(ref parts) => {
+                let parts: Vec<String> = parts.iter()
+                    .map(|part| Markup::from_json(part).value)
+                    .filter(|part| !part.trim().is_empty())
+                    .collect();
+                Markup::markdown(parts.join("\n\n"))
+            }
+            json::Value::Object(_) => {
+                let field = |name: &str| value.find(name).and_then(|field| field.as_str());
+                match (field("kind"), field("language"), field("value")) {
+                    (Some("plaintext"), _, Some(value)) => Markup::plain_text(value.to_string()),
+                    (Some(_), _, Some(value)) => Markup::markdown(value.to_string()),
+                    (None, Some(language), Some(value)) => {
+                        Markup::markdown(format!("```{}\n{}\n```", language, value))
+                    }
+                    _ => Markup::markdown(String::new()),
+                }
+            }
+            _ => Markup::markdown(String::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.trim().is_empty()
+    }
+
+    /// Renders for a terminal, wrapping paragraphs at `width` columns. Code blocks are never
+    /// wrapped.
+    pub fn render(&self, width: usize, style: RenderStyle) -> String {
+        match self.format {
+            MarkupFormat::PlainText => render_plain_text(&self.value, width),
+            MarkupFormat::Markdown => render_markdown(&self.value, width, style),
+        }
+    }
+}
+
+/// The documentation of a completion item, if it has any.
+pub fn completion_documentation(item: &CompletionItem) -> Option<Markup> {
+    json::to_value(item)
+        .find("documentation")
+        .map(Markup::from_json)
+        .and_then(|markup| if markup.is_empty() { None } else { Some(markup) })
+}
+
+struct Word {
+    rendered: String,
+    width: usize,
+}
+
+/// The number of columns a character takes in a terminal: two for wide characters, like CJK
+/// ideographs and most emoji, and none for combining marks and zero width characters.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300...0x036F | 0x200B...0x200F | 0x20D0...0x20FF | 0xFE00...0xFE0F => 0,
+        0x1100...0x115F | 0x2E80...0x303E | 0x3041...0x33FF | 0x3400...0x4DBF | 0x4E00...0x9FFF |
+        0xA000...0xA4CF | 0xAC00...0xD7A3 | 0xF900...0xFAFF | 0xFE30...0xFE4F | 0xFF00...0xFF60 |
+        0xFFE0...0xFFE6 | 0x1F300...0x1F64F | 0x1F900...0x1F9FF | 0x20000...0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+fn plain_words(text: &str) -> Vec<Word> {
+    text.split_whitespace()
+        .map(|word| {
+            Word {
+                rendered: word.to_string(),
+                width: display_width(word),
+            }
+        })
+        .collect()
+}
+
+fn wrap(words: &[Word], width: usize, first_prefix: &str, rest_prefix: &str, lines: &mut Vec<String>) {
+    let mut line = first_prefix.to_string();
+    let mut line_width = display_width(first_prefix);
+    let mut is_empty = true;
+    for word in words {
+        if !is_empty && line_width + 1 + word.width > width {
+            lines.push(line);
+            line = rest_prefix.to_string();
+            line_width = display_width(rest_prefix);
+            is_empty = true;
+        }
+        if !is_empty {
+            line.push(' ');
+            line_width += 1;
+        }
+        line.push_str(&word.rendered);
+        line_width += word.width;
+        is_empty = false;
+    }
+    lines.push(line);
+}
+
+fn render_plain_text(text: &str, width: usize) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            lines.push(String::new());
+        } else {
+            let indent = &line[..line.len() - line.trim_left().len()];
+            wrap(&plain_words(line), width, indent, indent, &mut lines);
+        }
+    }
+    lines.join("\n")
+}
+
+#[derive(Debug, PartialEq)]
+enum Inline {
+    Text(String),
+    Code(String),
+    Strong(String),
+    Emphasis(String),
+    Link { text: String, url: String },
+    Url(String),
+}
+
+fn is_escapable(c: char) -> bool {
+    "\\`*_{}[]()#+-.!<>|~".contains(c)
+}
+
+fn find_from(chars: &[char], from: usize, pattern: &[char]) -> Option<usize> {
+    if pattern.len() > chars.len() {
+        return None;
+    }
+    (from..chars.len() - pattern.len() + 1).find(|&index| &chars[index..index + pattern.len()] == pattern)
+}
+
+/// The closing `delimiter` of an emphasis, searched from `from`. Underscores only close an
+/// emphasis at the end of a word.
+fn find_closing(chars: &[char], from: usize, delimiter: &[char]) -> Option<usize> {
+    let mut search = from;
+    while let Some(index) = find_from(chars, search, delimiter) {
+        let after = index + delimiter.len();
+        if delimiter[0] == '*' || chars.get(after).map_or(true, |c| !c.is_alphanumeric()) {
+            return Some(index);
+        }
+        search = index + 1;
+    }
+    None
+}
+
+/// Parses `[text](url)` starting at `start`, and returns the text, the url and the index after
+/// the link.
+fn parse_link(chars: &[char], start: usize) -> Option<(String, String, usize)> {
+    let mut depth = 0;
+    let mut close = None;
+    for index in start..chars.len() {
+        match chars[index] {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(index);
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    let close = match close {
+        Some(close) if chars.get(close + 1) == Some(&'(') => close,
+        _ => return None,
+    };
+    let end = match find_from(chars, close + 2, &[')']) {
+        Some(end) => end,
+        None => return None,
+    };
+    let text: String = chars[start + 1..close].iter().filter(|&&c| c != '`').cloned().collect();
+    let destination: String = chars[close + 2..end].iter().cloned().collect();
+    // Drop the optional title.
+    let url = destination.split_whitespace().next().unwrap_or("").to_string();
+    Some((text, url, end + 1))
+}
+
+fn parse_inline(text: &str) -> Vec<Inline> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut inlines = Vec::new();
+    let mut plain = String::new();
+    let mut i = 0;
+
+    macro_rules! push {
+        ($inline:expr) => {{
+            if !plain.is_empty() {
+                inlines.push(Inline::Text(plain.clone()));
+                plain.clear();
+            }
+            inlines.push($inline);
+        }}
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+        // Underscores inside words, like in `snake_case`, are not delimiters.
+        let can_open = c == '*' || i == 0 || !chars[i - 1].is_alphanumeric();
+        match c {
+            '\\' if next.map_or(false, is_escapable) => {
+                plain.push(chars[i + 1]);
+                i += 2;
+            }
+            '`' => {
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let fence: Vec<char> = vec!['`'; run];
+                let mut search = i + run;
+                let mut close = None;
+                while let Some(index) = find_from(&chars, search, &fence) {
+                    let after = index + run;
+                    if chars.get(after) != Some(&'`') {
+                        close = Some(index);
+                        break;
+                    }
+                    search = after + chars[after..].iter().take_while(|&&c| c == '`').count();
+                }
+                match close {
+                    Some(close) => {
+                        let code: String = chars[i + run..close].iter().cloned().collect();
+                        let code = if code.starts_with(' ') && code.ends_with(' ') && code.len() > 1 {
+                            code[1..code.len() - 1].to_string()
+                        } else {
+                            code
+                        };
+                        push!(Inline::Code(code));
+                        i = close + run;
+                    }
+                    None => {
+                        plain.extend(fence);
+                        i += run;
+                    }
+                }
+            }
+            '*' | '_' if can_open && next == Some(c) => {
+                match find_closing(&chars, i + 2, &[c, c]) {
+                    Some(close) if close > i + 2 => {
+                        push!(Inline::Strong(chars[i + 2..close].iter().filter(|&&c| c != '`').cloned().collect()));
+                        i = close + 2;
+                    }
+                    _ => {
+                        plain.push(c);
+                        plain.push(c);
+                        i += 2;
+                    }
+                }
+            }
+            '*' | '_' if can_open && next.map_or(false, |next| !next.is_whitespace()) => {
+                match find_closing(&chars, i + 1, &[c]) {
+                    Some(close) => {
+                        push!(Inline::Emphasis(chars[i + 1..close].iter().filter(|&&c| c != '`').cloned().collect()));
+                        i = close + 1;
+                    }
+                    None => {
+                        plain.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            '!' if next == Some('[') => {
+                match parse_link(&chars, i + 1) {
+                    // Images are rendered as their alternative text.
+                    Some((text, _, end)) => {
+                        plain.push_str(&text);
+                        i = end;
+                    }
+                    None => {
+                        plain.push('!');
+                        i += 1;
+                    }
+                }
+            }
+            '[' => {
+                match parse_link(&chars, i) {
+                    Some((text, url, end)) => {
+                        push!(Inline::Link {
+                            text: text,
+                            url: url,
+                        });
+                        i = end;
+                    }
+                    None => {
+                        plain.push('[');
+                        i += 1;
+                    }
+                }
+            }
+            '<' => {
+                let close = find_from(&chars, i + 1, &['>']);
+                let url: Option<String> = close.map(|close| chars[i + 1..close].iter().cloned().collect());
+                match url {
+                    Some(ref url) if (url.starts_with("http://") || url.starts_with("https://")) &&
+                                     !url.contains(' ') => {
+                        push!(Inline::Url(url.clone()));
+                        i = close.unwrap() + 1;
+                    }
+                    _ => {
+                        plain.push('<');
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                plain.push(c);
+                i += 1;
+            }
+        }
+    }
+    if !plain.is_empty() {
+        inlines.push(Inline::Text(plain));
+    }
+    inlines
+}
+
+/// Renders inline markdown into words, styled with `default_code` where no other style applies.
+fn inline_words(text: &str, style: RenderStyle, default_code: Option<&'static str>) -> Vec<Word> {
+    let mut spans: Vec<(String, Option<&'static str>)> = Vec::new();
+    for inline in parse_inline(text) {
+        match inline {
+            Inline::Text(text) => spans.push((text, default_code)),
+            Inline::Code(text) => spans.push((text, Some(CODE))),
+            Inline::Strong(text) => spans.push((text, Some(BOLD))),
+            Inline::Emphasis(text) => spans.push((text, Some(ITALIC))),
+            Inline::Url(url) => spans.push((url, Some(UNDERLINE))),
+            Inline::Link { text, url } => {
+                if text.is_empty() || text == url {
+                    spans.push((url, Some(UNDERLINE)));
+                } else {
+                    spans.push((text, Some(UNDERLINE)));
+                    spans.push((format!(" ({})", url), Some(DIM)));
+                }
+            }
+        }
+    }
+
+    let mut words = Vec::new();
+    let mut current = Word {
+        rendered: String::new(),
+        width: 0,
+    };
+    for (text, code) in spans {
+        let code = match style {
+            RenderStyle::Plain => None,
+            RenderStyle::Ansi => code,
+        };
+        let mut segment = String::new();
+        for c in text.chars() {
+            if c.is_whitespace() {
+                push_segment(&mut current, &segment, code);
+                segment.clear();
+                if current.width > 0 {
+                    words.push(current);
+                    current = Word {
+                        rendered: String::new(),
+                        width: 0,
+                    };
+                }
+            } else {
+                segment.push(c);
+            }
+        }
+        push_segment(&mut current, &segment, code);
+    }
+    if current.width > 0 {
+        words.push(current);
+    }
+    words
+}
+
+fn push_segment(word: &mut Word, segment: &str, code: Option<&'static str>) {
+    if segment.is_empty() {
+        return;
+    }
+    match code {
+        Some(code) => {
+            word.rendered.push_str(code);
+            word.rendered.push_str(segment);
+            word.rendered.push_str(RESET);
+        }
+        None => word.rendered.push_str(segment),
+    }
+    word.width += display_width(segment);
+}
+
+#[derive(Debug, PartialEq)]
+enum Block {
+    Paragraph(String),
+    Heading(usize, String),
+    /// The lines of a code block, and the language of its fence if any.
+    Code(Option<String>, Vec<String>),
+    ListItem {
+        indent: usize,
+        marker: String,
+        text: String,
+    },
+    Quote(String),
+    Rule,
+}
+
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if level >= 1 && level <= 6 && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim().trim_right_matches('#').trim().to_string()))
+    } else {
+        None
+    }
+}
+
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && "-*_".contains(chars[0]) && chars.iter().all(|&c| c == chars[0])
+}
+
+fn list_item(line: &str) -> Option<(usize, String, String)> {
+    let indent = line.chars().take_while(|&c| c == ' ').count();
+    let rest = &line[indent..];
+    for bullet in &["- ", "* ", "+ "] {
+        if rest.starts_with(bullet) {
+            return Some((indent, "-".to_string(), rest[2..].trim().to_string()));
+        }
+    }
+    let digits = rest.chars().take_while(|c| c.is_digit(10)).count();
+    let after = &rest[digits..];
+    if digits >= 1 && digits <= 9 && (after.starts_with(". ") || after.starts_with(") ")) {
+        return Some((indent, format!("{}.", &rest[..digits]), after[2..].trim().to_string()));
+    }
+    None
+}
+
+fn parse_blocks(markdown: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut fence: Option<(String, Option<String>, Vec<String>)> = None;
+    // Whether a plain line continues the last block rather than starting a paragraph.
+    let mut continues = false;
+
+    for line in markdown.lines() {
+        if let Some((marker, language, mut lines)) = fence.take() {
+            if line.trim_left().starts_with(marker.as_str()) {
+                blocks.push(Block::Code(language, lines));
+            } else {
+                lines.push(line.to_string());
+                fence = Some((marker, language, lines));
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let language = trimmed[3..].split_whitespace().next().map(str::to_string);
+            fence = Some((trimmed[..3].to_string(), language, Vec::new()));
+            continues = false;
+        } else if trimmed.is_empty() {
+            continues = false;
+        } else if let Some((level, text)) = heading(trimmed) {
+            blocks.push(Block::Heading(level, text));
+            continues = false;
+        } else if is_rule(trimmed) {
+            blocks.push(Block::Rule);
+            continues = false;
+        } else if let Some((indent, marker, text)) = list_item(line) {
+            blocks.push(Block::ListItem {
+                indent: indent,
+                marker: marker,
+                text: text,
+            });
+            continues = true;
+        } else {
+            let (is_quote, text) = if trimmed.starts_with('>') {
+                (true, trimmed[1..].trim())
+            } else {
+                (false, trimmed)
+            };
+            if continues {
+                match blocks.last_mut() {
+                    Some(&mut Block::Quote(ref mut continued)) |
+                    Some(&mut Block::Paragraph(ref mut continued)) |
+                    Some(&mut Block::ListItem { text: ref mut continued, .. }) => {
+                        continued.push(' ');
+                        continued.push_str(text);
+                        continue;
+                    }
+                    _ => (),
+                }
+            }
+            blocks.push(if is_quote {
+                Block::Quote(text.to_string())
+            } else {
+                Block::Paragraph(text.to_string())
+            });
+            continues = true;
+        }
+    }
+    if let Some((_, language, lines)) = fence {
+        blocks.push(Block::Code(language, lines));
+    }
+    blocks
+}
+
+fn render_markdown(markdown: &str, width: usize, style: RenderStyle) -> String {
+    let ansi = style == RenderStyle::Ansi;
+    let mut lines = Vec::new();
+    let mut previous_was_item = None;
+
+    for block in parse_blocks(markdown) {
+        let is_item = match block {
+            Block::ListItem { .. } => true,
+            _ => false,
+        };
+        match previous_was_item {
+            Some(true) if is_item => (),
+            Some(_) => lines.push(String::new()),
+            None => (),
+        }
+        previous_was_item = Some(is_item);
+
+        match block {
+            Block::Paragraph(text) => wrap(&inline_words(&text, style, None), width, "", "", &mut lines),
+            Block::Heading(level, text) => {
+                let code = if level == 1 { HEADING } else { BOLD };
+                let first = lines.len();
+                wrap(&inline_words(&text, style, Some(code)), width, "", "", &mut lines);
+                if !ansi {
+                    let underline_width = lines[first..].iter().map(|line| display_width(line)).max().unwrap_or(0);
+                    let underline = if level == 1 { "=" } else { "-" };
+                    lines.push(underline.repeat(underline_width));
+                }
+            }
+            Block::Code(language, code_lines) => {
+                // The language labels the block, since it is not highlighted.
+                if let Some(language) = language {
+                    if ansi {
+                        lines.push(format!("{}{}{}", DIM, language, RESET));
+                    } else {
+                        lines.push(language);
+                    }
+                }
+                for line in code_lines {
+                    if ansi {
+                        lines.push(format!("    {}{}{}", CODE, line, RESET));
+                    } else {
+                        lines.push(format!("    {}", line));
+                    }
+                }
+            }
+            Block::ListItem { indent, marker, text } => {
+                let marker = if ansi && marker == "-" { "•".to_string() } else { marker };
+                let first_prefix = format!("{}{} ", " ".repeat(indent), marker);
+                let rest_prefix = " ".repeat(display_width(&first_prefix));
+                wrap(&inline_words(&text, style, None), width, &first_prefix, &rest_prefix, &mut lines);
+            }
+            Block::Quote(text) => {
+                let prefix = if ansi { format!("{}│{} ", DIM, RESET) } else { "> ".to_string() };
+                let words = inline_words(&text, style, None);
+                // The prefix is two columns wide, whatever its escape codes.
+                wrap(&words, width + display_width(&prefix) - 2, &prefix, &prefix, &mut lines);
+            }
+            Block::Rule => lines.push((if ansi { "─" } else { "-" }).repeat(width)),
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json as json;
+
+    #[test]
+    fn documentation_forms_are_normalized() {
+        let markup = |raw: &str| Markup::from_json(&json::from_str(raw).unwrap());
+        assert_eq!(markup(r#""*hi*""#), Markup::markdown("*hi*".to_string()));
+        assert_eq!(markup(r#"{"kind": "plaintext", "value": "hi"}"#), Markup::plain_text("hi".to_string()));
+        assert_eq!(markup(r#"[{"language": "rust", "value": "fn f()"}, "", "docs"]"#),
+                   Markup::markdown("```rust\nfn f()\n```\n\ndocs".to_string()));
+        assert!(markup("null").is_empty());
+    }
+
+    #[test]
+    fn markdown_is_rendered_as_plain_text() {
+        let markdown = "# Title\n\
+                        ```rust\n\
+                        fn some_function(argument: u8)\n\
+                        ```\n\
+                        Some `code`, **bold** and a [link](http://x.y \"title\") in a long paragraph.\n\
+                        ---\n\
+                        - first\n  item\n\
+                        1. second";
+        assert_eq!(Markup::markdown(markdown.to_string()).render(20, RenderStyle::Plain),
+                   "Title\n\
+                    =====\n\
+                    \nrust\n\
+                    \x20   fn some_function(argument: u8)\n\
+                    \nSome code, bold and\n\
+                    a link (http://x.y)\n\
+                    in a long paragraph.\n\
+                    \n--------------------\n\
+                    \n- first item\n\
+                    1. second");
+    }
+
+    #[test]
+    fn markdown_is_rendered_with_ansi_styles() {
+        let rendered = Markup::markdown("Use `x`".to_string()).render(80, RenderStyle::Ansi);
+        assert_eq!(rendered, "Use \x1b[36mx\x1b[0m");
+        assert_eq!(Markup::plain_text("  indented text\n\nnext".to_string()).render(10, RenderStyle::Ansi),
+                   "  indented\n  text\n\nnext");
+    }
+
+    #[test]
+    fn underscores_delimit_emphasis_outside_words() {
+        assert_eq!(parse_inline("_em_ and __strong__"),
+                   vec![Inline::Emphasis("em".to_string()),
+                        Inline::Text(" and ".to_string()),
+                        Inline::Strong("strong".to_string())]);
+        assert_eq!(parse_inline("snake_case_name and _open"),
+                   vec![Inline::Text("snake_case_name and _open".to_string())]);
+        assert_eq!(parse_inline("_a snake_case name_"),
+                   vec![Inline::Emphasis("a snake_case name".to_string())]);
+    }
+
+    #[test]
+    fn wide_characters_are_wrapped_by_their_width() {
+        assert_eq!(display_width("漢字 e\u{301}"), 6);
+        assert_eq!(Markup::plain_text("漢字漢字 かな".to_string()).render(10, RenderStyle::Plain),
+                   "漢字漢字\nかな");
+        assert_eq!(Markup::markdown("# 漢字".to_string()).render(10, RenderStyle::Plain), "漢字\n====");
+    }
+}
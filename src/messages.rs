@@ -17,6 +17,7 @@ use std::iter::{FromIterator, IntoIterator};
 #[derive(Debug)]
 pub enum IncomingMessage {
     Response(ResponseMessage),
+    Request(ServerRequest),
     Notification(ServerNotification),
     MultipleMessages(Vec<IncomingMessage>),
 }
@@ -24,6 +25,7 @@ pub enum IncomingMessage {
 #[derive(Debug)]
 pub enum OutgoingMessage {
     Request(RequestMessage),
+    Response(ServerResponse),
     Notification(Notification),
 }
 
@@ -35,13 +37,32 @@ impl FromIterator<IncomingMessage> for IncomingMessage {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct RpcError {
     pub code: i32,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<json::Value>,
 }
 
+impl RpcError {
+    pub fn new(code: i32, message: String) -> Self {
+        RpcError {
+            code: code,
+            message: message,
+            data: None,
+        }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        RpcError::new(-32601, format!("Unhandled method {}", method))
+    }
+
+    pub fn invalid_params(message: String) -> Self {
+        RpcError::new(-32602, message)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RequestMessage {
     pub jsonrpc: String,
@@ -69,6 +90,40 @@ pub struct ResponseMessage {
     pub error: Option<json::Value>,
 }
 
+/// A request sent by the server to the client. Unlike ours, their ids can be numbers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ServerRequest {
+    pub jsonrpc: String,
+    pub id: json::Value,
+    pub method: String,
+    pub params: Option<json::Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ServerResponse {
+    pub jsonrpc: String,
+    pub id: json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl ServerResponse {
+    pub fn new(id: json::Value, outcome: Result<json::Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        ServerResponse {
+            jsonrpc: "2.0".to_string(),
+            id: id,
+            result: result,
+            error: error,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Notification {
     pub jsonrpc: String,
@@ -86,9 +141,15 @@ impl Notification {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ServerNotification {
-    Other(Notification)
+    Other(Notification),
+    /// The server asked for the inlay hints of all documents to be requested again. The cached
+    /// hints have already been dropped.
+    InlayHintRefresh,
+    /// The server asked for the code lenses of all documents to be requested again. The cached
+    /// lenses have already been dropped.
+    CodeLensRefresh,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -97,3 +158,9 @@ pub struct ResponseError<T> {
     message: String,
     data: T,
 }
+
+impl<T> ResponseError<T> {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
@@ -0,0 +1,206 @@
+//! `Language` implementations for common language servers. The server binaries are looked up
+//! in the `PATH` when the preset is created.
+use error::{Error, Result};
+use language::Language;
+use serde_json as json;
+use serde_json::builder::ObjectBuilder;
+use std::env;
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// The path of an executable found in the `PATH`.
+pub fn find_executable(name: &str) -> Result<PathBuf> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    find_in_paths(name, &path).ok_or_else(|| {
+        Error::ServerNotFound {
+            name: name.to_string(),
+            path: path.to_string_lossy().into_owned(),
+        }
+    })
+}
+
+/// The first executable named `name` in a list of directories formatted like the `PATH`.
+fn find_in_paths(name: &str, paths: &OsStr) -> Option<PathBuf> {
+    let names = executable_names(name);
+    env::split_paths(paths)
+        .flat_map(|directory| names.iter().map(move |name| directory.join(name)))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// The file names of an executable: on Windows, its name with each extension of the `PATHEXT`,
+/// unless it already has one.
+#[cfg(windows)]
+fn executable_names(name: &str) -> Vec<String> {
+    if Path::new(name).extension().is_some() {
+        return vec![name.to_string()];
+    }
+    env::var("PATHEXT")
+        .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+        .split(';')
+        .filter(|extension| !extension.is_empty())
+        .map(|extension| format!("{}{}", name, extension))
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn executable_names(name: &str) -> Vec<String> {
+    vec![name.to_string()]
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+macro_rules! presets {
+    ( $( $(#[$attr:meta])* $name:ident: $binary:expr, [$($arg:expr),*], [$(($extension:expr, $language_id:expr)),*], [$($marker:expr),*], $options:expr;)+ ) => {$(
+        $(#[$attr])*
+        #[derive(Clone, Debug)]
+        pub struct $name {
+            binary: PathBuf,
+        }
+
+        impl $name {
+            /// Fails with `Error::ServerNotFound` if the server is not in the `PATH`.
+            pub fn new() -> Result<Self> {
+                Ok($name { binary: find_executable($binary)? })
+            }
+        }
+
+        impl Language for $name {
+            fn get_command(&self) -> Vec<String> {
+                let mut command = vec![self.binary.to_string_lossy().into_owned()];
+                command.extend(vec![$($arg.to_string()),*]);
+                command
+            }
+
+            fn initialization_options(&self) -> Option<json::Value> {
+                $options
+            }
+
+            fn language_ids(&self) -> Vec<(String, String)> {
+                vec![$(($extension.to_string(), $language_id.to_string())),*]
+            }
+
+            fn root_markers(&self) -> Vec<String> {
+                vec![$($marker.to_string()),*]
+            }
+        }
+    )*}
+}
+
+presets!(
+    /// rust-analyzer, with build scripts and procedural macros enabled.
+    RustAnalyzer: "rust-analyzer", [], [("rs", "rust")], ["Cargo.toml", ".git"],
+        Some(ObjectBuilder::new()
+            .insert_object("cargo", |cargo| cargo.insert_object("buildScripts", |scripts| scripts.insert("enable", true)))
+            .insert_object("procMacro", |macros| macros.insert("enable", true))
+            .build());
+    /// gopls, completing unimported packages and with placeholders for the parameters of
+    /// completed functions.
+    Gopls: "gopls", [], [("go", "go")], ["go.work", "go.mod", ".git"],
+        Some(ObjectBuilder::new()
+            .insert("usePlaceholders", true)
+            .insert("completeUnimported", true)
+            .build());
+    /// clangd, indexing the project in the background. It needs a `compile_commands.json`.
+    Clangd: "clangd", ["--background-index"],
+        [("c", "c"), ("h", "c"), ("cc", "cpp"), ("cpp", "cpp"), ("cxx", "cpp"), ("hh", "cpp"),
+         ("hpp", "cpp"), ("m", "objective-c"), ("mm", "objective-cpp")],
+        ["compile_commands.json", "compile_flags.txt", ".clangd", ".git"],
+        Some(ObjectBuilder::new().insert("clangdFileStatus", true).build());
+    Pyright: "pyright-langserver", ["--stdio"], [("py", "python"), ("pyi", "python")],
+        ["pyrightconfig.json", "pyproject.toml", "setup.py", ".git"], None;
+    Pylsp: "pylsp", [], [("py", "python")], ["pyproject.toml", "setup.py", ".git"], None;
+    TypeScriptLanguageServer: "typescript-language-server", ["--stdio"],
+        [("ts", "typescript"), ("mts", "typescript"), ("cts", "typescript"),
+         ("tsx", "typescriptreact"), ("js", "javascript"), ("mjs", "javascript"),
+         ("cjs", "javascript"), ("jsx", "javascriptreact")],
+        ["tsconfig.json", "jsconfig.json", "package.json", ".git"],
+        None;
+    BashLanguageServer: "bash-language-server", ["start"], [("sh", "shellscript"), ("bash", "shellscript")], [".git"], None;
+    LuaLanguageServer: "lua-language-server", [], [("lua", "lua")], [".luarc.json", ".git"], None;
+    Zls: "zls", [], [("zig", "zig")], ["build.zig", ".git"], None;
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::Error;
+    use language::Language;
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use uuid::Uuid;
+
+    #[test]
+    fn missing_servers_are_reported() {
+        match find_executable("surely-not-an-installed-language-server") {
+            Err(err @ Error::ServerNotFound { .. }) => {
+                let message = err.to_string();
+                assert!(message.starts_with("surely-not-an-installed-language-server was not found in the PATH"),
+                        "{}",
+                        message);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn executables_are_found_in_the_first_directory_having_them() {
+        let base = env::temp_dir().join(Uuid::new_v4().to_string());
+        let (first, second) = (base.join("first"), base.join("second"));
+        fs::create_dir_all(first.join("gopls")).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        File::create(first.join("zls")).unwrap();
+        File::create(second.join("gopls")).unwrap();
+        #[cfg(unix)]
+        fs::set_permissions(second.join("gopls"), fs::Permissions::from_mode(0o755)).unwrap();
+        let paths = env::join_paths(vec![&first, &second]).unwrap();
+
+        // Directories are skipped, and so are files without the executable bit on Unix.
+        assert_eq!(find_in_paths("gopls", &paths), Some(second.join("gopls")));
+        if cfg!(unix) {
+            assert_eq!(find_in_paths("zls", &paths), None);
+        }
+        assert_eq!(find_in_paths("clangd", &paths), None);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn executables_are_found_with_their_windows_extension() {
+        let directory = env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&directory).unwrap();
+        File::create(directory.join("gopls.EXE")).unwrap();
+        let paths = env::join_paths(vec![&directory]).unwrap();
+
+        assert_eq!(find_in_paths("gopls", &paths), Some(directory.join("gopls.EXE")));
+        assert_eq!(find_in_paths("gopls.EXE", &paths), Some(directory.join("gopls.EXE")));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn presets_describe_their_servers() {
+        let clangd = Clangd { binary: PathBuf::from("/usr/bin/clangd") };
+        assert_eq!(clangd.get_command(), vec!["/usr/bin/clangd", "--background-index"]);
+        assert_eq!(clangd.language_id(Path::new("src/main.cpp")), Some("cpp".to_string()));
+        assert_eq!(clangd.language_id(Path::new("src/main.rs")), None);
+        assert_eq!(clangd.initialization_options().unwrap().pointer("/clangdFileStatus"),
+                   Some(&json::Value::Bool(true)));
+        let rust_analyzer = RustAnalyzer { binary: PathBuf::from("rust-analyzer") };
+        assert_eq!(rust_analyzer.initialization_options().unwrap().pointer("/cargo/buildScripts/enable"),
+                   Some(&json::Value::Bool(true)));
+        assert!(Pyright { binary: PathBuf::from("pyright-langserver") }.initialization_options().is_none());
+    }
+}
@@ -0,0 +1,197 @@
+//! Renaming: finding out whether there is something to rename at the cursor before asking the
+//! user for a new name, and previewing the resulting edit.
+use completion::{is_word_char, word_before};
+use diff::unified_diff;
+use documents::{self, compare_positions, offset_at, position_at, Documents};
+use error::Result;
+use languageserver_types::{Position, Range, WorkspaceEdit};
+use serde::{Deserialize, Deserializer};
+use serde_json as json;
+use std::cmp::Ordering;
+use utils::from_json;
+
+/// The answer to `textDocument/prepareRename`, when there is something to rename.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PrepareRenameResponse {
+    Range(Range),
+    RangeWithPlaceholder { range: Range, placeholder: String },
+    /// The client should find what to rename by itself, as it would without `prepareRename`.
+    DefaultBehavior,
+}
+
+#[derive(Deserialize)]
+struct RangeWithPlaceholder {
+    range: Range,
+    placeholder: String,
+}
+
+impl Deserialize for PrepareRenameResponse {
+    fn deserialize<D>(deserializer: &mut D) -> ::std::result::Result<Self, D::Error>
+        where D: Deserializer
+    {
+        let value = json::Value::deserialize(deserializer)?;
+        if value.find("defaultBehavior").is_some() {
+            Ok(PrepareRenameResponse::DefaultBehavior)
+        } else if value.find("placeholder").is_some() {
+            from_json(value).map(|response: RangeWithPlaceholder| {
+                PrepareRenameResponse::RangeWithPlaceholder {
+                    range: response.range,
+                    placeholder: response.placeholder,
+                }
+            })
+        } else {
+            from_json(value).map(PrepareRenameResponse::Range)
+        }
+    }
+}
+
+/// What would be renamed, with the text to propose as the new name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenameTarget {
+    pub range: Range,
+    pub placeholder: String,
+}
+
+/// The range of the word around a position, if there is one.
+pub fn word_range_at(text: &str, position: &Position) -> Option<Range> {
+    let (start, _) = word_before(text, position);
+    let offset = offset_at(text, position);
+    let end = text[offset..]
+        .char_indices()
+        .find(|&(_, c)| !is_word_char(c))
+        .map_or(text.len(), |(index, _)| offset + index);
+    let end = position_at(text, end);
+    if start == end {
+        None
+    } else {
+        Some(Range {
+            start: start,
+            end: end,
+        })
+    }
+}
+
+/// Interprets the answer to `prepareRename` for the document `text`, if known. `None` means
+/// there is nothing to rename at `position`.
+pub fn rename_target(response: Option<PrepareRenameResponse>, text: Option<&str>, position: &Position) -> Option<RenameTarget> {
+    let text_of = |text: &str, range: &Range| {
+        text[offset_at(text, &range.start)..offset_at(text, &range.end)].to_string()
+    };
+    // An inverted range from the server does not point to anything to rename.
+    let inverted = match response {
+        Some(PrepareRenameResponse::Range(ref range)) |
+        Some(PrepareRenameResponse::RangeWithPlaceholder { ref range, .. }) => {
+            compare_positions(&range.start, &range.end) == Ordering::Greater
+        }
+        _ => false,
+    };
+    if inverted {
+        return None;
+    }
+    match response {
+        None => None,
+        Some(PrepareRenameResponse::RangeWithPlaceholder { range, placeholder }) => {
+            Some(RenameTarget {
+                range: range,
+                placeholder: placeholder,
+            })
+        }
+        Some(PrepareRenameResponse::Range(range)) => {
+            let placeholder = text.map_or(String::new(), |text| text_of(text, &range));
+            Some(RenameTarget {
+                range: range,
+                placeholder: placeholder,
+            })
+        }
+        Some(PrepareRenameResponse::DefaultBehavior) => {
+            text.and_then(|text| {
+                word_range_at(text, position).map(|range| {
+                    RenameTarget {
+                        placeholder: text_of(text, &range),
+                        range: range,
+                    }
+                })
+            })
+        }
+    }
+}
+
+/// The changes a `WorkspaceEdit` would make to a file, as a unified diff.
+#[derive(Clone, Debug)]
+pub struct FileDiff {
+    pub uri: String,
+    pub diff: String,
+}
+
+/// Computes the diffs of an edit without applying it. Open documents are diffed against their
+/// synchronized content, the other files against their content on disk.
+pub fn preview(documents: &Documents, edit: &WorkspaceEdit) -> Result<Vec<FileDiff>> {
+    let mut diffs = Vec::new();
+    for (uri, edits) in documents::workspace_edit_changes(edit)? {
+        let text = match documents.get(&uri) {
+            Some(document) => document.text.clone(),
+            None => documents::read_from_disk(&uri)?,
+        };
+        let edited = documents::apply_text_edits(&text, &edits);
+        diffs.push(FileDiff {
+            diff: unified_diff(&uri, &uri, &text, &edited, 3),
+            uri: uri,
+        });
+    }
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use documents::{Documents, OpenDocument};
+    use languageserver_types::{Position, WorkspaceEdit};
+    use serde_json as json;
+
+    #[test]
+    fn prepare_rename_responses_are_parsed() {
+        let range = r#"{"start": {"line": 0, "character": 4}, "end": {"line": 0, "character": 7}}"#;
+        let parse = |raw: &str| json::from_str::<Option<PrepareRenameResponse>>(raw).unwrap();
+
+        assert_eq!(parse("null"), None);
+        assert_eq!(parse(r#"{"defaultBehavior": true}"#), Some(PrepareRenameResponse::DefaultBehavior));
+        let text = Some("let foo = 1;");
+        let position = Position { line: 0, character: 5 };
+        assert_eq!(rename_target(parse(range), text, &position).unwrap().placeholder, "foo");
+        let with_placeholder = format!(r#"{{"range": {}, "placeholder": "bar"}}"#, range);
+        assert_eq!(rename_target(parse(&with_placeholder), text, &position).unwrap().placeholder, "bar");
+        assert_eq!(rename_target(parse(r#"{"defaultBehavior": true}"#), text, &position).unwrap().range,
+                   json::from_str(range).unwrap());
+        assert_eq!(rename_target(Some(PrepareRenameResponse::DefaultBehavior),
+                                 Some("a + b"),
+                                 &Position { line: 0, character: 2 }),
+                   None);
+
+        let inverted = r#"{"start": {"line": 0, "character": 7}, "end": {"line": 0, "character": 4}}"#;
+        assert_eq!(rename_target(parse(inverted), text, &position), None);
+        let inverted = format!(r#"{{"range": {}, "placeholder": "bar"}}"#, inverted);
+        assert_eq!(rename_target(parse(&inverted), text, &position), None);
+    }
+
+    #[test]
+    fn preview_diffs_open_documents() {
+        let mut documents = Documents::new();
+        documents.open(OpenDocument {
+            uri: "file:///main.rs".to_string(),
+            language_id: "rust".to_string(),
+            version: 1,
+            text: "fn foo() {}\nfn main() { foo() }\n".to_string(),
+        });
+        let edit: WorkspaceEdit = json::from_str(r#"{"changes": {"file:///main.rs": [
+            {"range": {"start": {"line": 0, "character": 3}, "end": {"line": 0, "character": 6}}, "newText": "bar"},
+            {"range": {"start": {"line": 1, "character": 12}, "end": {"line": 1, "character": 15}}, "newText": "bar"}
+        ]}}"#)
+            .unwrap();
+
+        let diffs = preview(&documents, &edit).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].diff,
+                   "--- file:///main.rs\n+++ file:///main.rs\n@@ -1,2 +1,2 @@\n\
+                    -fn foo() {}\n-fn main() { foo() }\n+fn bar() {}\n+fn main() { bar() }\n");
+    }
+}
@@ -0,0 +1,137 @@
+//! Finding the root of the project a file belongs to, from the files marking project roots
+//! (see `Language::root_markers`).
+use serde_json as json;
+use serde_json::builder::{ArrayBuilder, ObjectBuilder};
+use std::env;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// A path relative to the current directory made absolute, without resolving symbolic links.
+/// The path is returned as is if the current directory is unknown.
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    env::current_dir().map(|current| current.join(path)).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The root for a file: the nearest directory containing the first marker found, trying the
+/// markers in order. Returns `None` if no marker is found.
+pub fn find_root(file: &Path, markers: &[String]) -> Option<PathBuf> {
+    let file = absolute(file);
+    let start = if file.is_dir() { Some(file.as_path()) } else { file.parent() };
+    for marker in markers {
+        let mut directory = start;
+        while let Some(current) = directory {
+            if current.join(marker).exists() {
+                return Some(current.to_path_buf());
+            }
+            directory = current.parent();
+        }
+    }
+    None
+}
+
+/// Like `find_root`, falling back on the directory of the file.
+pub fn root_or_parent(file: &Path, markers: &[String]) -> PathBuf {
+    let file = absolute(file);
+    find_root(&file, markers).unwrap_or_else(|| {
+        if file.is_dir() {
+            file.to_path_buf()
+        } else {
+            file.parent().map_or_else(PathBuf::new, Path::to_path_buf)
+        }
+    })
+}
+
+/// Whether a server started for `server_root` can handle a file whose own root is `file_root`:
+/// the file must be inside the server's project.
+pub fn can_reuse(server_root: &Path, file_root: &Path) -> bool {
+    file_root.starts_with(server_root)
+}
+
+pub fn root_uri(root: &Path) -> Option<String> {
+    Url::from_directory_path(root).ok().map(|uri| uri.to_string())
+}
+
+/// A `WorkspaceFolder` object.
+pub fn workspace_folder(root: &Path) -> Option<json::Value> {
+    root_uri(root).map(|uri| {
+        let name = root.file_name().map_or_else(|| uri.clone(), |name| name.to_string_lossy().into_owned());
+        ObjectBuilder::new()
+            .insert("uri", uri)
+            .insert("name", name)
+            .build()
+    })
+}
+
+/// The folders an initialize request opens: its `workspaceFolders`, or else its `rootUri`.
+/// Returns `None` if it has neither.
+pub fn initialize_folders(params: &json::Value) -> Option<Vec<PathBuf>> {
+    if let Some(&json::Value::Array(ref folders)) = params.find("workspaceFolders") {
+        return Some(folders.iter()
+            .filter_map(|folder| folder.find("uri").and_then(|uri| uri.as_str()).and_then(uri_path))
+            .collect());
+    }
+    params.find("rootUri").and_then(|uri| uri.as_str()).and_then(uri_path).map(|root| vec![root])
+}
+
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    Url::parse(uri).ok().and_then(|uri| uri.to_file_path().ok())
+}
+
+/// The `workspaceFolders` of the initialize request for a root.
+pub fn workspace_folders(root: &Path) -> json::Value {
+    match workspace_folder(root) {
+        Some(folder) => ArrayBuilder::new().push(folder).build(),
+        None => json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{self, File};
+    use uuid::Uuid;
+
+    #[test]
+    fn roots_are_found_by_marker_priority() {
+        let workspace = env::temp_dir().join(Uuid::new_v4().to_string());
+        let krate = workspace.join("crates").join("parser");
+        fs::create_dir_all(krate.join("src")).unwrap();
+        fs::create_dir_all(workspace.join(".git")).unwrap();
+        File::create(krate.join("Cargo.toml")).unwrap();
+        let file = krate.join("src").join("lib.rs");
+        File::create(&file).unwrap();
+
+        let markers = vec!["Cargo.toml".to_string(), ".git".to_string()];
+        assert_eq!(find_root(&file, &markers), Some(krate.clone()));
+        assert_eq!(find_root(&file, &markers[1..]), Some(workspace.clone()));
+        assert_eq!(find_root(&file, &["go.mod".to_string()]), None);
+        assert_eq!(root_or_parent(&file, &["go.mod".to_string()]), krate.join("src"));
+
+        assert!(can_reuse(&workspace, &krate));
+        assert!(!can_reuse(&krate, &workspace));
+        assert_eq!(workspace_folders(&krate).pointer("/0/name"), Some(&json::Value::String("parser".to_string())));
+
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[test]
+    fn relative_files_get_absolute_roots() {
+        let current = env::current_dir().unwrap();
+        assert_eq!(absolute(Path::new("src/main.rs")), current.join("src/main.rs"));
+        assert_eq!(absolute(&current), current);
+        assert_eq!(root_or_parent(Path::new("surely-not-a-directory.rs"), &[]), current);
+        assert!(root_uri(&root_or_parent(Path::new("main.rs"), &[])).is_some());
+    }
+
+    #[test]
+    fn initialize_params_give_the_folders_to_handle() {
+        let folders = r#"{"rootUri": "file:///a", "workspaceFolders": [{"uri": "file:///b/", "name": "b"}]}"#;
+        assert_eq!(initialize_folders(&json::from_str(folders).unwrap()), Some(vec![PathBuf::from("/b")]));
+        let root = r#"{"rootUri": "file:///a", "workspaceFolders": null}"#;
+        assert_eq!(initialize_folders(&json::from_str(root).unwrap()), Some(vec![PathBuf::from("/a")]));
+        assert_eq!(initialize_folders(&json::from_str(r#"{"rootUri": null}"#).unwrap()), None);
+    }
+}
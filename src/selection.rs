@@ -0,0 +1,101 @@
+//! Selection ranges, for "expand selection" and "shrink selection".
+use languageserver_types::{Position, Range, TextDocumentIdentifier};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SelectionRangeParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+    pub positions: Vec<Position>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SelectionRange {
+    pub range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Box<SelectionRange>>,
+}
+
+/// Walks the chain of selection ranges around a cursor position, from the innermost range to
+/// the outermost. It starts with nothing selected.
+#[derive(Clone, Debug)]
+pub struct SelectionWalker {
+    ranges: Vec<Range>,
+    current: Option<usize>,
+}
+
+impl SelectionWalker {
+    pub fn new(selection: SelectionRange) -> Self {
+        let mut ranges: Vec<Range> = Vec::new();
+        let mut next = Some(Box::new(selection));
+        while let Some(selection) = next {
+            let selection = *selection;
+            if ranges.last() != Some(&selection.range) {
+                ranges.push(selection.range);
+            }
+            next = selection.parent;
+        }
+        SelectionWalker {
+            ranges: ranges,
+            current: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&Range> {
+        self.current.map(|index| &self.ranges[index])
+    }
+
+    /// Select the next enclosing range. Stays on the outermost range once it is reached.
+    pub fn expand(&mut self) -> Option<&Range> {
+        self.current = match self.current {
+            None if self.ranges.is_empty() => None,
+            None => Some(0),
+            Some(index) if index + 1 < self.ranges.len() => Some(index + 1),
+            Some(index) => Some(index),
+        };
+        self.current()
+    }
+
+    /// Select the previous, smaller range, or nothing after the innermost one.
+    pub fn shrink(&mut self) -> Option<&Range> {
+        self.current = match self.current {
+            Some(0) | None => None,
+            Some(index) => Some(index - 1),
+        };
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::{Position, Range};
+
+    fn range(start: u64, end: u64) -> Range {
+        Range {
+            start: Position { line: 0, character: start },
+            end: Position { line: 0, character: end },
+        }
+    }
+
+    #[test]
+    fn selection_walker_expands_and_shrinks() {
+        let selection = SelectionRange {
+            range: range(4, 7),
+            parent: Some(Box::new(SelectionRange {
+                range: range(4, 7),
+                parent: Some(Box::new(SelectionRange {
+                    range: range(0, 12),
+                    parent: None,
+                })),
+            })),
+        };
+        let mut walker = SelectionWalker::new(selection);
+
+        assert_eq!(walker.current(), None);
+        assert_eq!(walker.expand(), Some(&range(4, 7)));
+        assert_eq!(walker.expand(), Some(&range(0, 12)));
+        assert_eq!(walker.expand(), Some(&range(0, 12)));
+        assert_eq!(walker.shrink(), Some(&range(4, 7)));
+        assert_eq!(walker.shrink(), None);
+    }
+}
@@ -0,0 +1,226 @@
+//! Semantic tokens, as described in the 3.16 version of the protocol. Tokens are transmitted as
+//! a flat array of integers, five per token, each relative to the previous one.
+use languageserver_types::{Position, Range, TextDocumentIdentifier};
+use serde::{Deserialize, Deserializer};
+use serde_json as json;
+use std::cmp;
+use utils::from_json;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SemanticTokensParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SemanticTokensDeltaParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+    #[serde(rename = "previousResultId")]
+    pub previous_result_id: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SemanticTokensRangeParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SemanticTokens {
+    #[serde(rename = "resultId")]
+    pub result_id: Option<String>,
+    pub data: Vec<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SemanticTokensEdit {
+    pub start: u32,
+    #[serde(rename = "deleteCount")]
+    pub delete_count: u32,
+    pub data: Option<Vec<u32>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SemanticTokensDelta {
+    #[serde(rename = "resultId")]
+    pub result_id: Option<String>,
+    pub edits: Vec<SemanticTokensEdit>,
+}
+
+/// The response to `textDocument/semanticTokens/full/delta`: servers can send the full tokens
+/// instead of a delta.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SemanticTokensFullDeltaResult {
+    Tokens(SemanticTokens),
+    Delta(SemanticTokensDelta),
+}
+
+impl Deserialize for SemanticTokensFullDeltaResult {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        let value = json::Value::deserialize(deserializer)?;
+        if value.find("edits").is_some() {
+            from_json(value).map(SemanticTokensFullDeltaResult::Delta)
+        } else {
+            from_json(value).map(SemanticTokensFullDeltaResult::Tokens)
+        }
+    }
+}
+
+/// The standard token types. `decode` takes the names from the legend of the server, so these
+/// are only the ones advertised to it.
+pub const TOKEN_TYPES: &'static [&'static str] = &["namespace", "type", "class", "enum", "interface",
+                                                  "struct", "typeParameter", "parameter", "variable",
+                                                  "property", "enumMember", "event", "function",
+                                                  "method", "macro", "keyword", "modifier", "comment",
+                                                  "string", "number", "regexp", "operator",
+                                                  "decorator"];
+
+/// The standard token modifiers, see `TOKEN_TYPES`.
+pub const TOKEN_MODIFIERS: &'static [&'static str] = &["declaration", "definition", "readonly",
+                                                      "static", "deprecated", "abstract", "async",
+                                                      "modification", "documentation",
+                                                      "defaultLibrary"];
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SemanticTokensLegend {
+    #[serde(rename = "tokenTypes")]
+    pub token_types: Vec<String>,
+    #[serde(rename = "tokenModifiers")]
+    pub token_modifiers: Vec<String>,
+}
+
+/// A decoded token, with an absolute range and its type and modifiers looked up in the legend.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SemanticToken {
+    pub range: Range,
+    pub token_type: String,
+    pub modifiers: Vec<String>,
+}
+
+/// Apply the edits of a delta to the data of the previous result.
+pub fn apply_edits(previous: &[u32], edits: &[SemanticTokensEdit]) -> Vec<u32> {
+    let mut sorted: Vec<&SemanticTokensEdit> = edits.iter().collect();
+    sorted.sort_by(|a, b| b.start.cmp(&a.start));
+
+    let mut data = previous.to_vec();
+    for edit in sorted {
+        let start = cmp::min(edit.start as usize, data.len());
+        let end = cmp::min(start + edit.delete_count as usize, data.len());
+        let tail = data.split_off(end);
+        data.truncate(start);
+        if let Some(ref inserted) = edit.data {
+            data.extend_from_slice(inserted);
+        }
+        data.extend(tail);
+    }
+    data
+}
+
+/// Decode the relative integer encoding into absolute tokens. Tokens whose type is not in the
+/// legend are skipped.
+pub fn decode(data: &[u32], legend: &SemanticTokensLegend) -> Vec<SemanticToken> {
+    let mut tokens = Vec::with_capacity(data.len() / 5);
+    let mut line = 0u64;
+    let mut character = 0u64;
+
+    for chunk in data.chunks(5) {
+        if chunk.len() < 5 {
+            warn!("ignoring truncated semantic token {:?}", chunk);
+            break;
+        }
+        let (delta_line, delta_start, length, token_type, modifiers) =
+            (chunk[0] as u64, chunk[1] as u64, chunk[2] as u64, chunk[3], chunk[4]);
+
+        if delta_line == 0 {
+            character += delta_start;
+        } else {
+            line += delta_line;
+            character = delta_start;
+        }
+
+        let token_type = match legend.token_types.get(token_type as usize) {
+            Some(token_type) => token_type.clone(),
+            None => {
+                warn!("unknown semantic token type {}", token_type);
+                continue;
+            }
+        };
+
+        tokens.push(SemanticToken {
+            range: Range {
+                start: Position { line: line, character: character },
+                end: Position { line: line, character: character + length },
+            },
+            token_type: token_type,
+            modifiers: legend.token_modifiers
+                .iter()
+                .enumerate()
+                .filter(|&(bit, _)| bit < 32 && modifiers & (1 << bit) != 0)
+                .map(|(_, modifier)| modifier.clone())
+                .collect(),
+        });
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn legend() -> SemanticTokensLegend {
+        SemanticTokensLegend {
+            token_types: vec!["keyword".to_string(), "function".to_string()],
+            token_modifiers: vec!["declaration".to_string(), "static".to_string()],
+        }
+    }
+
+    #[test]
+    fn decode_makes_positions_absolute() {
+        let data = [1, 2, 2, 0, 0,
+                    0, 3, 4, 1, 3,
+                    2, 1, 5, 9, 0,
+                    0, 6, 1, 0, 2];
+        let tokens = decode(&data, &legend());
+
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].range.start, Position { line: 1, character: 2 });
+        assert_eq!(tokens[0].token_type, "keyword");
+        assert_eq!(tokens[1].range.start, Position { line: 1, character: 5 });
+        assert_eq!(tokens[1].range.end, Position { line: 1, character: 9 });
+        assert_eq!(tokens[1].modifiers, vec!["declaration", "static"]);
+        // The unknown token type still moves the cursor.
+        assert_eq!(tokens[2].range.start, Position { line: 3, character: 7 });
+        assert_eq!(tokens[2].modifiers, vec!["static"]);
+    }
+
+    #[test]
+    fn apply_edits_splices_from_the_end() {
+        let previous = [0, 0, 1, 0, 0, 1, 0, 1, 0, 0];
+        let edits = vec![SemanticTokensEdit { start: 5, delete_count: 5, data: None },
+                         SemanticTokensEdit { start: 0, delete_count: 1, data: Some(vec![2, 3]) }];
+        assert_eq!(apply_edits(&previous, &edits), vec![2, 3, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn full_delta_results_are_told_apart() {
+        let delta: SemanticTokensFullDeltaResult =
+            json::from_str(r#"{"resultId": "2", "edits": []}"#).unwrap();
+        assert_eq!(delta,
+                   SemanticTokensFullDeltaResult::Delta(SemanticTokensDelta {
+                       result_id: Some("2".to_string()),
+                       edits: vec![],
+                   }));
+        let full: SemanticTokensFullDeltaResult =
+            json::from_str(r#"{"data": [0, 0, 1, 0, 0]}"#).unwrap();
+        assert_eq!(full,
+                   SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+                       result_id: None,
+                       data: vec![0, 0, 1, 0, 0],
+                   }));
+    }
+}
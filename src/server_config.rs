@@ -0,0 +1,235 @@
+//! Servers described in a TOML file rather than in code:
+//!
+//! ```toml
+//! [servers.rust-analyzer]
+//! command = "rust-analyzer"
+//! root_markers = ["Cargo.toml"]
+//! languages = { rust = ["*.rs"] }
+//!
+//! [servers.rust-analyzer.settings.rust-analyzer]
+//! checkOnSave = true
+//! ```
+use configuration::toml_to_json;
+use error::{Error, Result};
+use glob::Pattern;
+use language::Language;
+use serde_json as json;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    /// The files marking the root of a project, like `Cargo.toml`. Defaults to `.git`.
+    #[serde(default)]
+    pub root_markers: Vec<String>,
+    /// The file globs handled by the server, by language id. Globs without a `/` are matched
+    /// against file names, the others against whole paths.
+    #[serde(default)]
+    pub languages: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub initialization_options: Option<json::Value>,
+    /// The settings answered to `workspace/configuration` requests.
+    #[serde(default)]
+    pub settings: Option<json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    servers: BTreeMap<String, ServerConfig>,
+}
+
+/// The servers of a configuration file, by name.
+#[derive(Clone, Debug)]
+pub struct ServersConfig {
+    servers: Vec<ConfiguredLanguage>,
+}
+
+impl ServersConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        ServersConfig::parse(&text)
+            .map_err(|err| match err {
+                Error::InvalidSettings(message) => {
+                    Error::InvalidSettings(format!("{}: {}", path.display(), message))
+                }
+                err => err,
+            })
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let value = text.parse::<toml::Value>()
+            .map_err(|err| Error::InvalidSettings(format!("{:?}", err)))?;
+        let file: ConfigFile = json::from_value(toml_to_json(value))
+            .map_err(|err| Error::InvalidSettings(err.to_string()))?;
+
+        let mut servers = Vec::new();
+        for (name, config) in file.servers {
+            servers.push(ConfiguredLanguage::new(name, config)?);
+        }
+        Ok(ServersConfig { servers: servers })
+    }
+
+    pub fn servers(&self) -> &[ConfiguredLanguage] {
+        &self.servers
+    }
+
+    pub fn server(&self, name: &str) -> Option<&ConfiguredLanguage> {
+        self.servers.iter().find(|server| server.name == name)
+    }
+
+    /// The first server, by name, handling a file.
+    pub fn for_file(&self, path: &Path) -> Option<&ConfiguredLanguage> {
+        self.servers.iter().find(|server| server.language_id(path).is_some())
+    }
+}
+
+/// A server from a configuration file.
+#[derive(Clone, Debug)]
+pub struct ConfiguredLanguage {
+    name: String,
+    config: ServerConfig,
+    patterns: Vec<(Pattern, String)>,
+}
+
+impl ConfiguredLanguage {
+    pub fn new(name: String, config: ServerConfig) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for (language_id, globs) in &config.languages {
+            for glob in globs {
+                let pattern = Pattern::new(glob)
+                    .map_err(|err| Error::InvalidSettings(format!("{}: {}: {}", name, glob, err)))?;
+                patterns.push((pattern, language_id.clone()));
+            }
+        }
+        Ok(ConfiguredLanguage {
+            name: name,
+            config: config,
+            patterns: patterns,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+}
+
+impl Language for ConfiguredLanguage {
+    fn get_command(&self) -> Vec<String> {
+        let mut command = vec![self.config.command.clone()];
+        command.extend(self.config.args.iter().cloned());
+        command
+    }
+
+    fn env(&self) -> Vec<(String, String)> {
+        self.config.env.iter().map(|(key, value)| (key.clone(), value.clone())).collect()
+    }
+
+    fn working_dir(&self) -> Option<PathBuf> {
+        self.config.working_dir.clone()
+    }
+
+    fn initialization_options(&self) -> Option<json::Value> {
+        self.config.initialization_options.clone()
+    }
+
+    fn root_markers(&self) -> Vec<String> {
+        if self.config.root_markers.is_empty() {
+            vec![".git".to_string()]
+        } else {
+            self.config.root_markers.clone()
+        }
+    }
+
+    fn settings(&self) -> Option<json::Value> {
+        self.config.settings.clone()
+    }
+
+    /// The extensions of the globs that are plain `*.extension` patterns.
+    fn language_ids(&self) -> Vec<(String, String)> {
+        self.patterns
+            .iter()
+            .filter_map(|&(ref pattern, ref language_id)| {
+                let glob = pattern.as_str();
+                if glob.starts_with("*.") && !glob[2..].contains(|c| "*?[]/".contains(c)) {
+                    Some((glob[2..].to_string(), language_id.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn language_id(&self, path: &Path) -> Option<String> {
+        let file_name = path.file_name().map(Path::new);
+        self.patterns
+            .iter()
+            .find(|&&(ref pattern, _)| {
+                if pattern.as_str().contains('/') {
+                    pattern.matches_path(path)
+                } else {
+                    file_name.map_or(false, |file_name| pattern.matches_path(file_name))
+                }
+            })
+            .map(|&(_, ref language_id)| language_id.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use language::Language;
+    use serde_json as json;
+    use std::path::Path;
+
+    const CONFIG: &'static str = r#"
+        [servers.typescript]
+        command = "typescript-language-server"
+        args = ["--stdio"]
+        env = { TSS_LOG = "-level verbose" }
+        languages = { typescript = ["*.ts"], typescriptreact = ["*.tsx", "app/**/*.jsx"] }
+
+        [servers.typescript.settings.typescript]
+        format = { semicolons = "remove" }
+    "#;
+
+    #[test]
+    fn servers_are_read_from_toml() {
+        let config = ServersConfig::parse(CONFIG).unwrap();
+        let typescript = config.server("typescript").unwrap();
+
+        assert_eq!(typescript.get_command(), vec!["typescript-language-server", "--stdio"]);
+        assert_eq!(typescript.env(), vec![("TSS_LOG".to_string(), "-level verbose".to_string())]);
+        assert_eq!(typescript.settings(),
+                   Some(json::from_str(r#"{"typescript": {"format": {"semicolons": "remove"}}}"#).unwrap()));
+        assert_eq!(typescript.language_ids(),
+                   vec![("ts".to_string(), "typescript".to_string()),
+                        ("tsx".to_string(), "typescriptreact".to_string())]);
+    }
+
+    #[test]
+    fn files_are_matched_with_globs() {
+        let config = ServersConfig::parse(CONFIG).unwrap();
+        let language_id = |path: &str| config.for_file(Path::new(path)).and_then(|server| server.language_id(Path::new(path)));
+
+        assert_eq!(language_id("/src/index.ts"), Some("typescript".to_string()));
+        assert_eq!(language_id("app/views/main.jsx"), Some("typescriptreact".to_string()));
+        assert_eq!(language_id("lib/main.jsx"), None);
+        assert!(ServersConfig::parse("[servers.broken]\ncommand = \"x\"\nlanguages = { x = [\"[\"] }").is_err());
+    }
+}
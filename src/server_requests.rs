@@ -0,0 +1,71 @@
+//! Handlers for the requests sent by the server to the client, like `workspace/configuration`.
+use messages::{RpcError, ServerRequest};
+use serde_json as json;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub type RequestHandler = Rc<Fn(json::Value) -> Result<json::Value, RpcError>>;
+
+pub struct ServerRequestHandlers {
+    handlers: HashMap<String, RequestHandler>,
+}
+
+impl ServerRequestHandlers {
+    pub fn new() -> Self {
+        ServerRequestHandlers { handlers: HashMap::new() }
+    }
+
+    /// Register a handler for `method`, replacing the previous one if any.
+    pub fn register<F>(&mut self, method: &str, handler: F)
+        where F: Fn(json::Value) -> Result<json::Value, RpcError> + 'static
+    {
+        self.handlers.insert(method.to_string(), Rc::new(handler));
+    }
+
+    pub fn unregister(&mut self, method: &str) -> bool {
+        self.handlers.remove(method).is_some()
+    }
+
+    /// The outcome to send back to the server. Requests without a handler are answered with a
+    /// `MethodNotFound` error. The registry is not borrowed while the handler runs, which holds
+    /// its own reference to it, so that it can register or unregister handlers itself.
+    pub fn handle(handlers: &RefCell<Self>, request: &ServerRequest) -> Result<json::Value, RpcError> {
+        let handler = handlers.borrow().handlers.get(&request.method).cloned();
+        match handler {
+            Some(handler) => handler(request.params.clone().unwrap_or(json::Value::Null)),
+            None => {
+                warn!("no handler for server request {}", request.method);
+                Err(RpcError::method_not_found(&request.method))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(method: &str) -> ServerRequest {
+        ServerRequest {
+            jsonrpc: "2.0".to_string(),
+            id: json::to_value(1),
+            method: method.to_string(),
+            params: None,
+        }
+    }
+
+    #[test]
+    fn handlers_can_register_handlers() {
+        let handlers = Rc::new(RefCell::new(ServerRequestHandlers::new()));
+        let registry = handlers.clone();
+        handlers.borrow_mut().register("first", move |_| {
+            registry.borrow_mut().register("second", |_| Ok(json::to_value(2)));
+            Ok(json::to_value(1))
+        });
+
+        assert!(ServerRequestHandlers::handle(&handlers, &request("second")).is_err());
+        assert_eq!(ServerRequestHandlers::handle(&handlers, &request("first")), Ok(json::to_value(1)));
+        assert_eq!(ServerRequestHandlers::handle(&handlers, &request("second")), Ok(json::to_value(2)));
+    }
+}
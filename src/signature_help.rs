@@ -0,0 +1,319 @@
+//! Signature help, with the context added in the 3.15 version of the protocol, and sessions
+//! that keep it up to date while the user types the arguments of a call.
+use languageserver_types::{Position, TextDocumentIdentifier};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json as json;
+use utils::from_json;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignatureHelpTriggerKind {
+    Invoked, // 1
+    TriggerCharacter, // 2
+    ContentChange, // 3
+}
+
+impl Serialize for SignatureHelpTriggerKind {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        match *self {
+            SignatureHelpTriggerKind::Invoked => serializer.serialize_u8(1),
+            SignatureHelpTriggerKind::TriggerCharacter => serializer.serialize_u8(2),
+            SignatureHelpTriggerKind::ContentChange => serializer.serialize_u8(3),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SignatureHelpContext {
+    #[serde(rename = "triggerKind")]
+    pub trigger_kind: SignatureHelpTriggerKind,
+    #[serde(rename = "triggerCharacter", skip_serializing_if = "Option::is_none")]
+    pub trigger_character: Option<String>,
+    #[serde(rename = "isRetrigger")]
+    pub is_retrigger: bool,
+    #[serde(rename = "activeSignatureHelp", skip_serializing_if = "Option::is_none")]
+    pub active_signature_help: Option<SignatureHelp>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SignatureHelpParams {
+    #[serde(rename = "textDocument")]
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<SignatureHelpContext>,
+}
+
+/// The label of a parameter: either a substring of the signature label, or its start and end
+/// offsets in the signature label, in UTF-16 code units.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterLabel {
+    Label(String),
+    Offsets(u64, u64),
+}
+
+impl Deserialize for ParameterLabel {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        match json::Value::deserialize(deserializer)? {
+            json::Value::String(label) => Ok(ParameterLabel::Label(label)),
+            offsets => from_json(offsets).map(|(start, end)| ParameterLabel::Offsets(start, end)),
+        }
+    }
+}
+
+impl Serialize for ParameterLabel {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer
+    {
+        match *self {
+            ParameterLabel::Label(ref label) => label.serialize(serializer),
+            ParameterLabel::Offsets(start, end) => (start, end).serialize(serializer),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ParameterInformation {
+    pub label: ParameterLabel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<json::Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignatureInformation {
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Vec<ParameterInformation>>,
+    #[serde(rename = "activeParameter", skip_serializing_if = "Option::is_none")]
+    pub active_parameter: Option<u64>,
+}
+
+impl SignatureInformation {
+    /// The byte ranges of the parameters in the signature label, ready to be highlighted.
+    /// Parameters given as a string are looked for after the previous parameter.
+    pub fn parameter_ranges(&self) -> Vec<Option<(usize, usize)>> {
+        let mut search_from = 0;
+        self.parameters
+            .as_ref()
+            .map_or(&[][..], |parameters| &parameters[..])
+            .iter()
+            .map(|parameter| {
+                let range = match parameter.label {
+                    ParameterLabel::Label(ref label) => {
+                        self.label[search_from..]
+                            .find(label.as_str())
+                            .map(|start| (search_from + start, search_from + start + label.len()))
+                    }
+                    ParameterLabel::Offsets(start, end) => {
+                        match (utf16_to_byte_offset(&self.label, start), utf16_to_byte_offset(&self.label, end)) {
+                            (Some(start), Some(end)) if start <= end => Some((start, end)),
+                            _ => None,
+                        }
+                    }
+                };
+                if let Some((_, end)) = range {
+                    search_from = end;
+                }
+                range
+            })
+            .collect()
+    }
+}
+
+fn utf16_to_byte_offset(text: &str, offset: u64) -> Option<usize> {
+    let mut utf16_offset = 0;
+    for (index, c) in text.char_indices() {
+        if utf16_offset == offset {
+            return Some(index);
+        }
+        utf16_offset += c.len_utf16() as u64;
+    }
+    if utf16_offset == offset {
+        Some(text.len())
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignatureHelp {
+    pub signatures: Vec<SignatureInformation>,
+    #[serde(rename = "activeSignature", skip_serializing_if = "Option::is_none")]
+    pub active_signature: Option<u64>,
+    #[serde(rename = "activeParameter", skip_serializing_if = "Option::is_none")]
+    pub active_parameter: Option<u64>,
+}
+
+impl SignatureHelp {
+    /// The active signature, defaulting to the first one when the server gives none or an out of
+    /// range one.
+    pub fn active_signature(&self) -> Option<&SignatureInformation> {
+        let index = self.active_signature.unwrap_or(0) as usize;
+        self.signatures.get(index).or_else(|| self.signatures.first())
+    }
+
+    /// The index of the active parameter of the active signature. The signature's own active
+    /// parameter takes precedence.
+    pub fn active_parameter(&self) -> Option<usize> {
+        self.active_signature()
+            .and_then(|signature| signature.active_parameter)
+            .or(self.active_parameter)
+            .map(|index| index as usize)
+    }
+
+    /// The byte range of the active parameter in the label of the active signature.
+    pub fn active_parameter_range(&self) -> Option<(usize, usize)> {
+        match (self.active_signature(), self.active_parameter()) {
+            (Some(signature), Some(index)) => {
+                signature.parameter_ranges().get(index).cloned().and_then(|range| range)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Tracks the signature help shown for a document, so that later requests are sent with the
+/// right context: requests made while signature help is showing are retriggers.
+#[derive(Debug)]
+pub struct SignatureHelpSession {
+    text_document: TextDocumentIdentifier,
+    trigger_characters: Vec<String>,
+    retrigger_characters: Vec<String>,
+    help: Option<SignatureHelp>,
+}
+
+impl SignatureHelpSession {
+    pub fn new(text_document: TextDocumentIdentifier, trigger_characters: Vec<String>, retrigger_characters: Vec<String>) -> Self {
+        SignatureHelpSession {
+            text_document: text_document,
+            trigger_characters: trigger_characters,
+            retrigger_characters: retrigger_characters,
+            help: None,
+        }
+    }
+
+    pub fn text_document(&self) -> &TextDocumentIdentifier {
+        &self.text_document
+    }
+
+    /// The signature help currently showing, if any.
+    pub fn help(&self) -> Option<&SignatureHelp> {
+        self.help.as_ref()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.help.is_some()
+    }
+
+    /// The context of a request made because the user asked for signature help.
+    pub fn invoked_context(&self) -> SignatureHelpContext {
+        SignatureHelpContext {
+            trigger_kind: SignatureHelpTriggerKind::Invoked,
+            trigger_character: None,
+            is_retrigger: self.is_active(),
+            active_signature_help: self.help.clone(),
+        }
+    }
+
+    /// The context of a request made after the user typed `typed`, or `None` if no request
+    /// should be made. While signature help is showing, any edit updates it; otherwise only the
+    /// server's trigger characters start it.
+    pub fn typed_context(&self, typed: &str) -> Option<SignatureHelpContext> {
+        let is_trigger = self.trigger_characters.iter().any(|c| c == typed);
+        let is_retrigger = is_trigger || self.retrigger_characters.iter().any(|c| c == typed);
+
+        if self.is_active() {
+            Some(SignatureHelpContext {
+                trigger_kind: if is_retrigger {
+                    SignatureHelpTriggerKind::TriggerCharacter
+                } else {
+                    SignatureHelpTriggerKind::ContentChange
+                },
+                trigger_character: if is_retrigger { Some(typed.to_string()) } else { None },
+                is_retrigger: true,
+                active_signature_help: self.help.clone(),
+            })
+        } else if is_trigger {
+            Some(SignatureHelpContext {
+                trigger_kind: SignatureHelpTriggerKind::TriggerCharacter,
+                trigger_character: Some(typed.to_string()),
+                is_retrigger: false,
+                active_signature_help: None,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Records the server's answer. An empty answer closes the session.
+    pub fn update(&mut self, help: Option<SignatureHelp>) {
+        self.help = help.and_then(|help| if help.signatures.is_empty() { None } else { Some(help) });
+    }
+
+    pub fn close(&mut self) {
+        self.help = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::TextDocumentIdentifier;
+    use serde_json as json;
+
+    fn help() -> SignatureHelp {
+        json::from_str(r#"{
+            "signatures": [
+                {"label": "fn f(a: u8, b: u8)", "parameters": [{"label": "a: u8"}, {"label": [12, 17]}]},
+                {"label": "fn f(a: ü8, a: u8)", "parameters": [{"label": "a"}, {"label": "a"}],
+                 "activeParameter": 1}
+            ],
+            "activeParameter": 1
+        }"#)
+            .unwrap()
+    }
+
+    #[test]
+    fn parameter_ranges_handle_both_label_forms() {
+        let help = help();
+        assert_eq!(help.signatures[0].parameter_ranges(), vec![Some((5, 10)), Some((12, 17))]);
+        assert_eq!(help.signatures[1].parameter_ranges(), vec![Some((5, 6)), Some((13, 14))]);
+    }
+
+    #[test]
+    fn active_parameter_falls_back_on_the_signature_help() {
+        let mut help = help();
+        assert_eq!(help.active_parameter_range(), Some((12, 17)));
+        help.active_signature = Some(7);
+        assert_eq!(help.active_signature().unwrap().label, "fn f(a: u8, b: u8)");
+        help.active_signature = Some(1);
+        assert_eq!(help.active_parameter_range(), Some((13, 14)));
+    }
+
+    #[test]
+    fn sessions_retrigger_while_active() {
+        let text_document: TextDocumentIdentifier =
+            json::from_str(r#"{"uri": "file:///main.rs"}"#).unwrap();
+        let mut session = SignatureHelpSession::new(text_document, vec!["(".to_string()], vec![",".to_string()]);
+
+        assert!(session.typed_context(",").is_none());
+        let context = session.typed_context("(").unwrap();
+        assert_eq!(context.trigger_kind, SignatureHelpTriggerKind::TriggerCharacter);
+        assert!(!context.is_retrigger);
+
+        session.update(Some(help()));
+        let context = session.typed_context(",").unwrap();
+        assert_eq!(context.trigger_character, Some(",".to_string()));
+        assert!(context.is_retrigger && context.active_signature_help.is_some());
+        assert_eq!(session.typed_context("x").unwrap().trigger_kind,
+                   SignatureHelpTriggerKind::ContentChange);
+
+        session.update(Some(SignatureHelp { signatures: Vec::new(), active_signature: None, active_parameter: None }));
+        assert!(!session.is_active());
+    }
+}
@@ -0,0 +1,628 @@
+//! The snippet syntax used by completion items with an `insertTextFormat` of `Snippet`, e.g.
+//! `for ${1:i} in ${2|iter,into_iter|}() {\n\t$0\n}`.
+//!
+//! Parsing never fails: anything that is not valid snippet syntax is taken literally.
+use documents::position_at;
+use languageserver_types::{CompletionItem, Position, Range};
+use serde_json as json;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    Text(String),
+    /// `$1` or `${1}`
+    Tabstop(u32),
+    /// `${1:default}`
+    Placeholder(u32, Vec<Element>),
+    /// `${1|one,two|}`
+    Choice(u32, Vec<String>),
+    /// `$NAME`, `${NAME}`, `${NAME:default}` or `${NAME/regex/format/options}`
+    Variable {
+        name: String,
+        default: Option<Vec<Element>>,
+        transform: Option<Transform>,
+    },
+}
+
+/// A regular expression replacement on the value of a variable. Transforms are parsed, but not
+/// applied: the variable value is inserted as is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub regex: String,
+    pub format: String,
+    pub options: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snippet {
+    pub elements: Vec<Element>,
+}
+
+pub fn parse(input: &str) -> Snippet {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+        unclosed: HashMap::new(),
+    };
+    Snippet { elements: parser.parse_elements(false) }
+}
+
+/// Whether a completion item's insert text is a snippet.
+pub fn is_snippet(item: &CompletionItem) -> bool {
+    json::to_value(item).find("insertTextFormat").and_then(|format| format.as_u64()) == Some(2)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// The placeholders whose contents ran to the end of the input, by the position of their `$`,
+    /// with the position their contents start at.
+    unclosed: HashMap<usize, usize>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).cloned()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_elements(&mut self, in_placeholder: bool) -> Vec<Element> {
+        let mut elements = Vec::new();
+        let mut text = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => {
+                    match self.peek_at(1) {
+                        Some(escaped) if escaped == '$' || escaped == '}' || escaped == '\\' => {
+                            text.push(escaped);
+                            self.pos += 2;
+                        }
+                        _ => {
+                            text.push('\\');
+                            self.pos += 1;
+                        }
+                    }
+                }
+                '}' if in_placeholder => break,
+                '$' if self.unclosed.contains_key(&self.pos) => {
+                    // Known not to be closed: the opening is text, and the contents are parsed
+                    // without it.
+                    let contents = self.unclosed[&self.pos];
+                    text.extend(self.chars[self.pos..contents].iter().cloned());
+                    self.pos = contents;
+                }
+                '$' => {
+                    let start = self.pos;
+                    match self.parse_dollar() {
+                        Some(element) => {
+                            if !text.is_empty() {
+                                elements.push(Element::Text(text.clone()));
+                                text.clear();
+                            }
+                            elements.push(element);
+                        }
+                        // The element ran to the end of the input, so no enclosing
+                        // placeholder can be closed either. The contents of unclosed
+                        // placeholders are parsed again once, without them; anything else is
+                        // taken literally, rather than scanned again from each enclosing `$`.
+                        None if self.peek().is_none() => {
+                            if in_placeholder {
+                                return elements;
+                            }
+                            if self.unclosed.contains_key(&start) {
+                                self.pos = start;
+                            } else {
+                                text.extend(self.chars[start..].iter().cloned());
+                            }
+                        }
+                        None => {
+                            self.pos = start + 1;
+                            text.push('$');
+                        }
+                    }
+                }
+                _ => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            elements.push(Element::Text(text));
+        }
+        elements
+    }
+
+    fn parse_int(&mut self) -> u32 {
+        let mut value = 0u32;
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+            value = value.saturating_mul(10).saturating_add(digit);
+            self.pos += 1;
+        }
+        value
+    }
+
+    fn parse_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c == '_' || c.is_alphanumeric() {
+                name.push(c);
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        name
+    }
+
+    /// Parses what follows a `$`. Returns `None` if it is not a valid snippet element.
+    fn parse_dollar(&mut self) -> Option<Element> {
+        let start = self.pos;
+        self.pos += 1;
+        match self.peek() {
+            Some(c) if c.is_digit(10) => Some(Element::Tabstop(self.parse_int())),
+            Some(c) if c == '_' || c.is_alphabetic() => {
+                Some(Element::Variable {
+                    name: self.parse_name(),
+                    default: None,
+                    transform: None,
+                })
+            }
+            Some('{') => {
+                self.pos += 1;
+                self.parse_braced(start)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_braced(&mut self, start: usize) -> Option<Element> {
+        match self.peek() {
+            Some(c) if c.is_digit(10) => {
+                let index = self.parse_int();
+                if self.eat('}') {
+                    Some(Element::Tabstop(index))
+                } else if self.eat(':') {
+                    let contents = self.pos;
+                    let children = self.parse_elements(true);
+                    if self.eat('}') {
+                        Some(Element::Placeholder(index, children))
+                    } else {
+                        self.unclosed.insert(start, contents);
+                        None
+                    }
+                } else if self.eat('|') {
+                    let choices = self.parse_choices();
+                    if choices.is_some() && self.eat('|') && self.eat('}') {
+                        choices.map(|choices| Element::Choice(index, choices))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            }
+            Some(c) if c == '_' || c.is_alphabetic() => {
+                let name = self.parse_name();
+                if self.eat('}') {
+                    Some(Element::Variable {
+                        name: name,
+                        default: None,
+                        transform: None,
+                    })
+                } else if self.eat(':') {
+                    let contents = self.pos;
+                    let children = self.parse_elements(true);
+                    if self.eat('}') {
+                        Some(Element::Variable {
+                            name: name,
+                            default: Some(children),
+                            transform: None,
+                        })
+                    } else {
+                        self.unclosed.insert(start, contents);
+                        None
+                    }
+                } else if self.eat('/') {
+                    let regex = self.parse_until('/');
+                    let format = regex.as_ref().and_then(|_| self.parse_until('/'));
+                    let options = format.as_ref().and_then(|_| self.parse_until('}'));
+                    match (regex, format, options) {
+                        (Some(regex), Some(format), Some(options)) => {
+                            Some(Element::Variable {
+                                name: name,
+                                default: None,
+                                transform: Some(Transform {
+                                    regex: regex,
+                                    format: format,
+                                    options: options,
+                                }),
+                            })
+                        }
+                        _ => None,
+                    }
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The comma-separated options of a choice, up to (and not including) the closing `|`.
+    fn parse_choices(&mut self) -> Option<Vec<String>> {
+        let mut choices = Vec::new();
+        let mut choice = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => {
+                    match self.peek_at(1) {
+                        Some(escaped) if escaped == ',' || escaped == '|' || escaped == '\\' => {
+                            choice.push(escaped);
+                            self.pos += 2;
+                        }
+                        _ => {
+                            choice.push('\\');
+                            self.pos += 1;
+                        }
+                    }
+                }
+                ',' => {
+                    choices.push(choice.clone());
+                    choice.clear();
+                    self.pos += 1;
+                }
+                '|' => {
+                    choices.push(choice);
+                    return Some(choices);
+                }
+                _ => {
+                    choice.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        None
+    }
+
+    /// The raw text up to an unescaped delimiter, which is consumed.
+    fn parse_until(&mut self, delimiter: char) -> Option<String> {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == '\\' {
+                if let Some(escaped) = self.peek() {
+                    if escaped != delimiter {
+                        text.push('\\');
+                    }
+                    text.push(escaped);
+                    self.pos += 1;
+                }
+            } else if c == delimiter {
+                return Some(text);
+            } else {
+                text.push(c);
+            }
+        }
+        None
+    }
+}
+
+/// The values of the variables usable in snippets, like `TM_FILENAME`.
+#[derive(Clone, Debug, Default)]
+pub struct SnippetContext {
+    variables: HashMap<String, String>,
+}
+
+impl SnippetContext {
+    pub fn new() -> Self {
+        SnippetContext::default()
+    }
+
+    /// The file variables (`TM_FILENAME`, `TM_FILENAME_BASE`, `TM_DIRECTORY`, `TM_FILEPATH`)
+    /// for a document, if its URI points to a local file.
+    pub fn for_document(uri: &str) -> Self {
+        match Url::parse(uri).ok().and_then(|url| url.to_file_path().ok()) {
+            Some(path) => SnippetContext::for_file(&path),
+            None => SnippetContext::new(),
+        }
+    }
+
+    pub fn for_file(path: &Path) -> Self {
+        let mut context = SnippetContext::new();
+        context.set("TM_FILEPATH", &path.to_string_lossy());
+        if let Some(file_name) = path.file_name() {
+            context.set("TM_FILENAME", &file_name.to_string_lossy());
+        }
+        if let Some(stem) = path.file_stem() {
+            context.set("TM_FILENAME_BASE", &stem.to_string_lossy());
+        }
+        if let Some(directory) = path.parent() {
+            context.set("TM_DIRECTORY", &directory.to_string_lossy());
+        }
+        context
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.variables.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(|value| value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tabstop {
+    pub index: u32,
+    /// Byte ranges in the expanded text. A tabstop used several times has several ranges.
+    pub ranges: Vec<(usize, usize)>,
+    pub choices: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expansion {
+    pub text: String,
+    /// In the order the cursor goes through them: by index, with the final tabstop `$0` last.
+    pub tabstops: Vec<Tabstop>,
+}
+
+impl Expansion {
+    /// The tabstop ranges as document ranges, for text inserted at `start`.
+    pub fn tabstop_ranges(&self, start: &Position) -> Vec<(u32, Vec<Range>)> {
+        let to_position = |offset: usize| {
+            let relative = position_at(&self.text, offset);
+            if relative.line == 0 {
+                Position {
+                    line: start.line,
+                    character: start.character + relative.character,
+                }
+            } else {
+                Position {
+                    line: start.line + relative.line,
+                    character: relative.character,
+                }
+            }
+        };
+        self.tabstops
+            .iter()
+            .map(|tabstop| {
+                let ranges = tabstop.ranges
+                    .iter()
+                    .map(|&(start, end)| {
+                        Range {
+                            start: to_position(start),
+                            end: to_position(end),
+                        }
+                    })
+                    .collect();
+                (tabstop.index, ranges)
+            })
+            .collect()
+    }
+}
+
+/// Expand a snippet into plain text. Tabstops repeating a placeholder get its text. Unknown
+/// variables without a default are inserted by name and become placeholders, after the
+/// numbered ones.
+pub fn expand(snippet: &Snippet, context: &SnippetContext) -> Expansion {
+    let mut defaults = HashMap::new();
+    collect_defaults(&snippet.elements, context, &mut defaults);
+
+    let mut expander = Expander::new(context, defaults);
+    expander.expand(&snippet.elements);
+
+    let Expander { text, mut tabstops, unknown_variables, .. } = expander;
+
+    let mut next_index = tabstops.keys().cloned().max().unwrap_or(0) + 1;
+    for range in unknown_variables {
+        tabstops.insert(next_index,
+                        Tabstop {
+                            index: next_index,
+                            ranges: vec![range],
+                            choices: None,
+                        });
+        next_index += 1;
+    }
+
+    let end = text.len();
+    let last = tabstops.remove(&0).unwrap_or(Tabstop {
+        index: 0,
+        ranges: vec![(end, end)],
+        choices: None,
+    });
+    let mut ordered: Vec<Tabstop> = tabstops.into_iter().map(|(_, tabstop)| tabstop).collect();
+    ordered.push(last);
+
+    Expansion {
+        text: text,
+        tabstops: ordered,
+    }
+}
+
+fn collect_defaults(elements: &[Element], context: &SnippetContext, defaults: &mut HashMap<u32, String>) {
+    for element in elements {
+        match *element {
+            Element::Placeholder(index, ref children) => {
+                if !defaults.contains_key(&index) {
+                    let mut expander = Expander::new(context, HashMap::new());
+                    expander.expand(children);
+                    defaults.insert(index, expander.text);
+                }
+                collect_defaults(children, context, defaults);
+            }
+            Element::Choice(index, ref choices) => {
+                if !defaults.contains_key(&index) {
+                    defaults.insert(index, choices.first().cloned().unwrap_or_default());
+                }
+            }
+            Element::Variable { default: Some(ref children), .. } => {
+                collect_defaults(children, context, defaults)
+            }
+            _ => (),
+        }
+    }
+}
+
+struct Expander<'a> {
+    context: &'a SnippetContext,
+    defaults: HashMap<u32, String>,
+    text: String,
+    tabstops: BTreeMap<u32, Tabstop>,
+    unknown_variables: Vec<(usize, usize)>,
+}
+
+impl<'a> Expander<'a> {
+    fn new(context: &'a SnippetContext, defaults: HashMap<u32, String>) -> Self {
+        Expander {
+            context: context,
+            defaults: defaults,
+            text: String::new(),
+            tabstops: BTreeMap::new(),
+            unknown_variables: Vec::new(),
+        }
+    }
+
+    fn add_range(&mut self, index: u32, start: usize, choices: Option<Vec<String>>) {
+        let end = self.text.len();
+        let tabstop = self.tabstops.entry(index).or_insert(Tabstop {
+            index: index,
+            ranges: Vec::new(),
+            choices: None,
+        });
+        tabstop.ranges.push((start, end));
+        if tabstop.choices.is_none() {
+            tabstop.choices = choices;
+        }
+    }
+
+    fn expand(&mut self, elements: &[Element]) {
+        for element in elements {
+            let start = self.text.len();
+            match *element {
+                Element::Text(ref text) => self.text.push_str(text),
+                Element::Tabstop(index) => {
+                    if let Some(default) = self.defaults.get(&index) {
+                        self.text.push_str(default);
+                    }
+                    self.add_range(index, start, None);
+                }
+                Element::Placeholder(index, ref children) => {
+                    self.expand(children);
+                    self.add_range(index, start, None);
+                }
+                Element::Choice(index, ref choices) => {
+                    if let Some(first) = choices.first() {
+                        self.text.push_str(first);
+                    }
+                    self.add_range(index, start, Some(choices.clone()));
+                }
+                Element::Variable { ref name, ref default, .. } => {
+                    let context = self.context;
+                    match (context.get(name), default.as_ref()) {
+                        (Some(value), _) => self.text.push_str(value),
+                        (None, Some(children)) => self.expand(children),
+                        (None, None) => {
+                            self.text.push_str(name);
+                            self.unknown_variables.push((start, self.text.len()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn snippets_are_parsed() {
+        let snippet = parse(r"fn ${1:name}($2) -> ${3|u8,u16|} {\n\t$0\}\$x ${TM_FILENAME/(.*)/$1/g}");
+        assert_eq!(snippet.elements,
+                   vec![Element::Text("fn ".to_string()),
+                        Element::Placeholder(1, vec![Element::Text("name".to_string())]),
+                        Element::Text("(".to_string()),
+                        Element::Tabstop(2),
+                        Element::Text(") -> ".to_string()),
+                        Element::Choice(3, vec!["u8".to_string(), "u16".to_string()]),
+                        Element::Text(r" {\n\t".to_string()),
+                        Element::Tabstop(0),
+                        Element::Text("}$x ".to_string()),
+                        Element::Variable {
+                            name: "TM_FILENAME".to_string(),
+                            default: None,
+                            transform: Some(Transform {
+                                regex: "(.*)".to_string(),
+                                format: "$1".to_string(),
+                                options: "g".to_string(),
+                            }),
+                        }]);
+    }
+
+    #[test]
+    fn invalid_syntax_is_taken_literally() {
+        assert_eq!(parse("cost: $ 5, ${1:unclosed").elements,
+                   vec![Element::Text("cost: $ 5, ${1:unclosed".to_string())]);
+    }
+
+    #[test]
+    fn nested_unclosed_placeholders_are_parsed_in_linear_time() {
+        let input = "${1:".repeat(40) + "text";
+        assert_eq!(parse(&input).elements, vec![Element::Text(input.clone())]);
+
+        let input = "${1:".repeat(40) + "${2:closed}";
+        assert_eq!(parse(&input).elements,
+                   vec![Element::Text("${1:".repeat(40)),
+                        Element::Placeholder(2, vec![Element::Text("closed".to_string())])]);
+    }
+
+    #[test]
+    fn elements_inside_unclosed_placeholders_are_parsed() {
+        assert_eq!(parse("${1:foo ${2:bar}").elements,
+                   vec![Element::Text("${1:foo ".to_string()),
+                        Element::Placeholder(2, vec![Element::Text("bar".to_string())])]);
+        assert_eq!(parse("${NAME:a ${1:b $2").elements,
+                   vec![Element::Text("${NAME:a ${1:b ".to_string()), Element::Tabstop(2)]);
+    }
+
+    #[test]
+    fn snippets_are_expanded() {
+        let context = SnippetContext::for_file(Path::new("/src/main.rs"));
+        let snippet = parse("${1:x} = $1 + ${2:${TM_FILENAME_BASE}} $UNKNOWN;");
+        let expansion = expand(&snippet, &context);
+
+        assert_eq!(expansion.text, "x = x + main UNKNOWN;");
+        assert_eq!(expansion.tabstops,
+                   vec![Tabstop { index: 1, ranges: vec![(0, 1), (4, 5)], choices: None },
+                        Tabstop { index: 2, ranges: vec![(8, 12)], choices: None },
+                        Tabstop { index: 3, ranges: vec![(13, 20)], choices: None },
+                        Tabstop { index: 0, ranges: vec![(21, 21)], choices: None }]);
+    }
+
+    #[test]
+    fn tabstop_ranges_follow_lines() {
+        let expansion = expand(&parse("if $1 {\n    $0\n}"), &SnippetContext::new());
+        let ranges = expansion.tabstop_ranges(&Position { line: 2, character: 4 });
+        assert_eq!(ranges[0].1[0].start, Position { line: 2, character: 7 });
+        assert_eq!(ranges[1].1[0].start, Position { line: 3, character: 4 });
+    }
+}
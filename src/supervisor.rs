@@ -0,0 +1,409 @@
+//! Restarts a server when it crashes. The restarted server is initialized with the same
+//! parameters, and gets the open documents and the settings of the previous one.
+use configuration::Settings;
+use documents::Documents;
+use error::{Error, Result};
+use futures::Future;
+use futures::future::{self, Either};
+use roots;
+use serde_json as json;
+use serde_json::builder::ObjectBuilder;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::{Handle, Timeout};
+use types::*;
+use LanguageServer;
+
+/// How often, and how fast, a crashed server is restarted.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    /// The number of restarts after which the server is left dead.
+    pub max_restarts: u32,
+    /// The delay before the first restart, doubled for each of the next ones.
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// How long a server must run for its crash not to count against the restarts of the
+    /// previous ones.
+    pub reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: 5,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            reset_after: Duration::from_secs(600),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// The delay before a restart, given the number of previous ones.
+    fn delay(&self, restarts: u32) -> Duration {
+        let factor = 2u32.pow(cmp::min(restarts, 16));
+        cmp::min(self.initial_delay * factor, self.max_delay)
+    }
+
+    /// The restarts counted against `max_restarts` when a server that ran for `uptime` exits.
+    fn restarts_counted(&self, restarts: u32, uptime: Duration) -> u32 {
+        if uptime >= self.reset_after {
+            0
+        } else {
+            restarts
+        }
+    }
+}
+
+struct Inner {
+    server: Rc<RefCell<LanguageServer>>,
+    policy: RestartPolicy,
+    restarts: Cell<u32>,
+    /// When the current server was started.
+    started: Cell<Instant>,
+    stopped: Cell<bool>,
+    /// Whether a restart is scheduled or running.
+    restarting: Cell<bool>,
+    hooks: RefCell<Vec<Box<Fn(&LanguageServer)>>>,
+    handle: Handle,
+}
+
+/// Watches a server, and replaces it with a new one when it exits. The server is replaced in
+/// place, so the `Rc` returned by `server` stays valid, but the `notifications` stream of the
+/// previous server ends and must be taken again. Server request handlers and client features
+/// enabled on the server must be set up again with `on_restart`.
+///
+/// The requests waiting for an answer when the server exits fail with
+/// `Error::ServerRestarted`.
+pub struct Supervisor {
+    inner: Rc<Inner>,
+}
+
+impl Supervisor {
+    pub fn new(server: LanguageServer, policy: RestartPolicy) -> Self {
+        Supervisor::for_shared(Rc::new(RefCell::new(server)), policy)
+    }
+
+    /// Supervises a server shared with other owners, like a `Workspace`.
+    pub fn for_shared(server: Rc<RefCell<LanguageServer>>, policy: RestartPolicy) -> Self {
+        server.borrow().supervised.set(true);
+        let handle = server.borrow().handle.clone();
+        let inner = Rc::new(Inner {
+            server: server,
+            policy: policy,
+            restarts: Cell::new(0),
+            started: Cell::new(Instant::now()),
+            stopped: Cell::new(false),
+            restarting: Cell::new(false),
+            hooks: RefCell::new(Vec::new()),
+            handle: handle,
+        });
+        watch(inner.clone());
+        Supervisor { inner: inner }
+    }
+
+    pub fn server(&self) -> Rc<RefCell<LanguageServer>> {
+        self.inner.server.clone()
+    }
+
+    /// The number of recent restarts, counted against `RestartPolicy::max_restarts`.
+    pub fn restarts(&self) -> u32 {
+        self.inner.restarts.get()
+    }
+
+    /// Registers a function called with each restarted server before it is initialized, e.g. to
+    /// register its server request handlers.
+    pub fn on_restart<F>(&self, hook: F)
+        where F: Fn(&LanguageServer) + 'static
+    {
+        self.inner.hooks.borrow_mut().push(Box::new(hook));
+    }
+
+    /// Sends a request or a notification to the current server. Unlike with `server`, messages
+    /// that could not be written because the server exited also fail with
+    /// `Error::ServerRestarted`, as long as the server is going to be restarted.
+    pub fn request<F, R>(&self, request: F) -> impl 'static + Future<Item=R::Item, Error=Error>
+        where F: FnOnce(&mut LanguageServer) -> R,
+              R: 'static + Future<Error=Error>
+    {
+        let exited = self.inner.server.borrow().exited.clone();
+        let inner = self.inner.clone();
+        let requested = request(&mut self.inner.server.borrow_mut());
+        requested.map_err(move |err| {
+            if exited.get() && restart_pending(&inner) {
+                Error::ServerRestarted
+            } else {
+                err
+            }
+        })
+    }
+
+    /// Stops restarting the server, without shutting it down.
+    pub fn stop(&self) {
+        self.inner.stopped.set(true);
+        self.inner.server.borrow().supervised.set(false);
+    }
+
+    /// Shuts the server down and tells it to exit, without restarting it.
+    pub fn shutdown(&self) -> impl 'static + Future<Item=(), Error=Error> {
+        self.stop();
+        let server = self.inner.server.clone();
+        let shutdown = server.borrow_mut().shutdown(());
+        shutdown.and_then(move |_| {
+            let exited = server.borrow().exit(());
+            exited
+        })
+    }
+}
+
+/// Whether the server is being restarted, or will be once its exit is noticed.
+fn restart_pending(inner: &Inner) -> bool {
+    if inner.stopped.get() {
+        return false;
+    }
+    inner.restarting.get() ||
+    inner.policy.restarts_counted(inner.restarts.get(), inner.started.get().elapsed()) < inner.policy.max_restarts
+}
+
+/// Schedules a restart for when the current server exits.
+fn watch(inner: Rc<Inner>) {
+    let exit = inner.server.borrow().on_exit();
+    let handle = inner.handle.clone();
+    handle.spawn(exit.then(move |_| -> ::std::result::Result<(), ()> {
+        schedule(inner);
+        Ok(())
+    }));
+}
+
+/// Restarts the server after a delay, unless the restart budget is spent.
+fn schedule(inner: Rc<Inner>) {
+    inner.restarting.set(false);
+    if inner.stopped.get() {
+        return;
+    }
+    let restarts = inner.policy.restarts_counted(inner.restarts.get(), inner.started.get().elapsed());
+    if restarts >= inner.policy.max_restarts {
+        warn!("the server exited, and was already restarted {} times: giving up", restarts);
+        return;
+    }
+    inner.restarts.set(restarts + 1);
+
+    let timeout = match Timeout::new(inner.policy.delay(restarts), &inner.handle) {
+        Ok(timeout) => timeout,
+        Err(err) => {
+            warn!("could not schedule a server restart: {:?}", err);
+            return;
+        }
+    };
+    inner.restarting.set(true);
+    let handle = inner.handle.clone();
+    let failed = inner.clone();
+    handle.spawn(timeout.map_err(Error::from)
+        .and_then(move |_| {
+            if inner.stopped.get() {
+                inner.restarting.set(false);
+                return Either::A(future::ok(()));
+            }
+            let restarted = restart(&inner);
+            Either::B(restarted.then(move |result| -> Result<()> {
+                match result {
+                    Ok(()) => {
+                        inner.restarting.set(false);
+                        inner.started.set(Instant::now());
+                        watch(inner);
+                    }
+                    Err(err) => {
+                        warn!("could not restart the server: {:?}", err);
+                        schedule(inner);
+                    }
+                }
+                Ok(())
+            }))
+        })
+        .map_err(move |err| {
+            failed.restarting.set(false);
+            warn!("could not restart the server: {:?}", err)
+        }));
+}
+
+/// Replaces the server with a new one, and brings it to the state of the previous one.
+fn restart(inner: &Rc<Inner>) -> Box<Future<Item=(), Error=Error>> {
+    // Reaps the crashed server, or kills the one that failed to initialize.
+    inner.server.borrow().kill();
+    let respawned = respawn(&inner.server.borrow(), inner.handle.clone());
+    match respawned {
+        Ok(server) => *inner.server.borrow_mut() = server,
+        Err(err) => return Box::new(future::err(err)),
+    }
+    for hook in inner.hooks.borrow().iter() {
+        hook(&inner.server.borrow());
+    }
+
+    let params = match inner.server.borrow().initialize_params.clone() {
+        Some(params) => params,
+        // The previous server was never initialized: neither is this one.
+        None => return Box::new(future::ok(())),
+    };
+    let folders = inner.server.borrow().workspace_folders();
+    let params = with_current_folders(params, &folders);
+    let initialize = inner.server.borrow_mut().initialize_with(params);
+    let server = inner.server.clone();
+    Box::new(initialize.and_then(move |result| {
+        match result {
+            Ok(_) => {
+                let replayed = replay(&server.borrow());
+                Either::A(replayed)
+            }
+            Err(err) => Either::B(future::err(Error::InitializeFailed(err.message().to_string()))),
+        }
+    }))
+}
+
+/// Starts the server again, sharing the documents and commands of the previous one.
+fn respawn(previous: &LanguageServer, handle: Handle) -> Result<LanguageServer> {
+    let mut server = LanguageServer::spawn(previous.language.clone(), previous.root.clone(), handle)?;
+    server.supervised.set(true);
+    server.documents = previous.documents.clone();
+    server.commands = previous.commands.clone();
+    *server.settings.borrow_mut() = previous.settings.borrow().clone();
+    *server.workspace_folders.borrow_mut() = previous.workspace_folders.borrow().clone();
+    *server.client_capabilities.borrow_mut() = previous.client_capabilities.borrow().clone();
+    server.initialize_params = previous.initialize_params.clone();
+    Ok(server)
+}
+
+/// The initialize parameters, with the workspace folders added or removed since.
+fn with_current_folders(mut params: json::Value, folders: &[PathBuf]) -> json::Value {
+    if let Some(params) = params.as_object_mut() {
+        if params.get("workspaceFolders").map_or(false, |folders| *folders != json::Value::Null) {
+            let folders: Vec<json::Value> = folders.iter()
+                .filter_map(|folder| roots::workspace_folder(folder))
+                .collect();
+            params.insert("workspaceFolders".to_string(), json::Value::Array(folders));
+        }
+    }
+    params
+}
+
+/// The notifications bringing a restarted server to the state of the previous one:
+/// `initialized`, then `didOpen` for the open documents, and the current settings.
+fn replay_notifications(documents: &Documents, settings: &Settings) -> Vec<(&'static str, json::Value)> {
+    let mut notifications = vec![("initialized", ObjectBuilder::new().build())];
+    for document in documents.iter() {
+        let params = ObjectBuilder::new()
+            .insert_object("textDocument", |text_document| {
+                text_document.insert("uri", &document.uri)
+                    .insert("languageId", &document.language_id)
+                    .insert("version", document.version)
+                    .insert("text", &document.text)
+            })
+            .build();
+        notifications.push((NOTIFICATION__DidOpenTextDocument, params));
+    }
+    if *settings.global() != json::Value::Null {
+        let params = ObjectBuilder::new().insert("settings", settings.global()).build();
+        notifications.push((NOTIFICATION__WorkspaceChangeConfiguration, params));
+    }
+    notifications
+}
+
+fn replay(server: &LanguageServer) -> Box<Future<Item=(), Error=Error>> {
+    let notifications: Vec<_> = replay_notifications(&server.documents.borrow(), &server.settings.borrow())
+        .into_iter()
+        .map(|(method, params)| server.notify_with_params(method, params))
+        .collect();
+    Box::new(future::join_all(notifications).map(|_| ()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use documents::OpenDocument;
+    use std::path::Path;
+    use tokio_core::reactor::Core;
+    use Language;
+
+    struct Cat;
+
+    impl Language for Cat {
+        fn get_command(&self) -> Vec<String> {
+            vec!["cat".to_string()]
+        }
+    }
+
+    #[test]
+    fn restart_delay_doubles_up_to_the_maximum() {
+        let policy = RestartPolicy {
+            max_restarts: 10,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            reset_after: Duration::from_secs(60),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(40), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn restarts_are_forgotten_after_a_stable_run() {
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.restarts_counted(3, Duration::from_secs(5)), 3);
+        assert_eq!(policy.restarts_counted(3, policy.reset_after), 0);
+    }
+
+    #[test]
+    fn initialize_params_get_the_current_folders() {
+        let params = json::from_str(r#"{"rootUri": "file:///a", "workspaceFolders": [{"uri": "file:///a", "name": "a"}]}"#)
+            .unwrap();
+        let folders = vec![Path::new("/a").to_path_buf(), Path::new("/b").to_path_buf()];
+        let params = with_current_folders(params, &folders);
+        assert_eq!(params.pointer("/workspaceFolders/1/uri"), Some(&json::to_value("file:///b/")));
+        assert_eq!(params.pointer("/rootUri"), Some(&json::to_value("file:///a")));
+
+        // Servers initialized without workspace folders are not given any.
+        let params = json::from_str(r#"{"rootUri": "file:///a", "workspaceFolders": null}"#).unwrap();
+        assert_eq!(with_current_folders(params, &folders).pointer("/workspaceFolders"),
+                   Some(&json::Value::Null));
+    }
+
+    #[test]
+    fn replay_reopens_documents_and_sends_settings() {
+        let mut documents = Documents::new();
+        documents.open(OpenDocument {
+            uri: "file:///main.rs".to_string(),
+            language_id: "rust".to_string(),
+            version: 7,
+            text: "fn main() {}".to_string(),
+        });
+        let mut settings = Settings::new();
+        settings.set("gopls.staticcheck", json::Value::Bool(true));
+
+        let notifications = replay_notifications(&documents, &settings);
+        let methods: Vec<&str> = notifications.iter().map(|&(method, _)| method).collect();
+        assert_eq!(methods,
+                   vec!["initialized", "textDocument/didOpen", "workspace/didChangeConfiguration"]);
+        assert_eq!(notifications[1].1.pointer("/textDocument/version"), Some(&json::to_value(7)));
+        assert_eq!(notifications[1].1.pointer("/textDocument/text"), Some(&json::to_value("fn main() {}")));
+        assert_eq!(notifications[2].1.pointer("/settings/gopls/staticcheck"), Some(&json::Value::Bool(true)));
+    }
+
+    #[test]
+    fn restarts_are_pending_until_stopped_or_out_of_budget() {
+        let core = Core::new().unwrap();
+        let server = LanguageServer::for_file(Cat, Path::new("main.rs"), core.handle()).unwrap();
+        let supervisor = Supervisor::new(server, RestartPolicy::default());
+        assert!(restart_pending(&supervisor.inner));
+        supervisor.inner.restarts.set(RestartPolicy::default().max_restarts);
+        assert!(!restart_pending(&supervisor.inner));
+        supervisor.inner.restarting.set(true);
+        assert!(restart_pending(&supervisor.inner));
+        supervisor.stop();
+        assert!(!restart_pending(&supervisor.inner));
+        supervisor.server().borrow().kill();
+    }
+}
@@ -0,0 +1,257 @@
+//! Document symbols, which servers return either flat or nested, and an outline tree built from
+//! either form.
+use documents::{compare_positions, range_contains, range_contains_range};
+use languageserver_types::{Position, Range, SymbolInformation, SymbolKind};
+use serde::{Deserialize, Deserializer};
+use serde_json as json;
+use serde_json::builder::ObjectBuilder;
+use std::cmp::Ordering;
+use utils::from_json;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DocumentSymbol {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<bool>,
+    pub range: Range,
+    #[serde(rename = "selectionRange")]
+    pub selection_range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<DocumentSymbol>>,
+}
+
+/// The response to `textDocument/documentSymbol`.
+#[derive(Clone, Debug)]
+pub enum DocumentSymbolResponse {
+    Flat(Vec<SymbolInformation>),
+    Nested(Vec<DocumentSymbol>),
+}
+
+impl Deserialize for DocumentSymbolResponse {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        let value = json::Value::deserialize(deserializer)?;
+        // `SymbolInformation`s have a location, `DocumentSymbol`s a range.
+        let is_flat = match value {
+            json::Value::Null => return Ok(DocumentSymbolResponse::Flat(Vec::new())),
+            json::Value::Array(ref symbols) => {
+                symbols.first().map_or(true, |symbol| symbol.find("location").is_some())
+            }
+            _ => true,
+        };
+        if is_flat {
+            from_json(value).map(DocumentSymbolResponse::Flat)
+        } else {
+            from_json(value).map(DocumentSymbolResponse::Nested)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OutlineNode {
+    /// The symbol, without its children.
+    pub symbol: DocumentSymbol,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// The symbols of a document as a tree, with the children of each node ordered by position.
+#[derive(Clone, Debug)]
+pub struct OutlineTree {
+    nodes: Vec<OutlineNode>,
+    roots: Vec<usize>,
+}
+
+impl OutlineTree {
+    pub fn new(response: DocumentSymbolResponse) -> Self {
+        match response {
+            DocumentSymbolResponse::Flat(symbols) => OutlineTree::from_flat(symbols),
+            DocumentSymbolResponse::Nested(symbols) => OutlineTree::from_nested(symbols),
+        }
+    }
+
+    pub fn from_nested(symbols: Vec<DocumentSymbol>) -> Self {
+        let mut tree = OutlineTree {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+        };
+        for symbol in sorted(symbols) {
+            let id = tree.push(symbol, None);
+            tree.roots.push(id);
+        }
+        tree
+    }
+
+    /// Build the tree from flat symbols, nesting them by range containment.
+    pub fn from_flat(symbols: Vec<SymbolInformation>) -> Self {
+        let mut symbols: Vec<DocumentSymbol> = symbols.into_iter()
+            .map(|symbol| {
+                DocumentSymbol {
+                    name: symbol.name,
+                    detail: None,
+                    kind: symbol.kind,
+                    deprecated: None,
+                    range: symbol.location.range.clone(),
+                    selection_range: symbol.location.range,
+                    children: None,
+                }
+            })
+            .collect();
+        // Parents before their children.
+        symbols.sort_by(|a, b| {
+            match compare_positions(&a.range.start, &b.range.start) {
+                Ordering::Equal => compare_positions(&b.range.end, &a.range.end),
+                ordering => ordering,
+            }
+        });
+
+        let mut tree = OutlineTree {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+        };
+        let mut stack: Vec<usize> = Vec::new();
+        for symbol in symbols {
+            while stack.last().map_or(false, |&open| {
+                !range_contains_range(&tree.nodes[open].symbol.range, &symbol.range)
+            }) {
+                stack.pop();
+            }
+            let parent = stack.last().cloned();
+            let id = tree.push(symbol, parent);
+            if parent.is_none() {
+                tree.roots.push(id);
+            }
+            stack.push(id);
+        }
+        tree
+    }
+
+    fn push(&mut self, mut symbol: DocumentSymbol, parent: Option<usize>) -> usize {
+        let children = symbol.children.take().unwrap_or_default();
+        let id = self.nodes.len();
+        self.nodes.push(OutlineNode {
+            symbol: symbol,
+            parent: parent,
+            children: Vec::new(),
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+        for child in sorted(children) {
+            self.push(child, Some(id));
+        }
+        id
+    }
+
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn node(&self, id: usize) -> &OutlineNode {
+        &self.nodes[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The node and its ancestors, from the outermost: the breadcrumbs of a symbol.
+    pub fn path(&self, id: usize) -> Vec<usize> {
+        let mut path = vec![id];
+        let mut current = self.nodes[id].parent;
+        while let Some(parent) = current {
+            path.push(parent);
+            current = self.nodes[parent].parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// The innermost symbol whose range contains a position.
+    pub fn enclosing(&self, position: &Position) -> Option<usize> {
+        let mut found = None;
+        let mut level = &self.roots;
+        while let Some(&id) = level.iter()
+            .find(|&&id| range_contains(&self.nodes[id].symbol.range, position)) {
+            found = Some(id);
+            level = &self.nodes[id].children;
+        }
+        found
+    }
+
+    /// The symbols in the flat form, in tree order, with the name of their parent as container.
+    pub fn flatten(&self, uri: &str) -> Vec<SymbolInformation> {
+        let mut flat = Vec::with_capacity(self.nodes.len());
+        let mut pending: Vec<usize> = self.roots.iter().rev().cloned().collect();
+        while let Some(id) = pending.pop() {
+            let node = &self.nodes[id];
+            let mut builder = ObjectBuilder::new()
+                .insert("name", &node.symbol.name)
+                .insert("kind", json::to_value(&node.symbol.kind))
+                .insert_object("location", |builder| {
+                    builder.insert("uri", uri).insert("range", json::to_value(&node.symbol.range))
+                });
+            if let Some(parent) = node.parent {
+                builder = builder.insert("containerName", &self.nodes[parent].symbol.name);
+            }
+            match json::from_value(builder.build()) {
+                Ok(symbol) => flat.push(symbol),
+                Err(err) => warn!("could not flatten symbol {:?}: {:?}", node.symbol, err),
+            }
+            pending.extend(node.children.iter().rev());
+        }
+        flat
+    }
+}
+
+fn sorted(mut symbols: Vec<DocumentSymbol>) -> Vec<DocumentSymbol> {
+    symbols.sort_by(|a, b| compare_positions(&a.range.start, &b.range.start));
+    symbols
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::Position;
+    use serde_json as json;
+
+    fn flat_symbols() -> DocumentSymbolResponse {
+        json::from_str(r#"[
+            {"name": "helper", "kind": 12, "location": {"uri": "file:///lib.rs",
+             "range": {"start": {"line": 3, "character": 4}, "end": {"line": 5, "character": 5}}}},
+            {"name": "Parser", "kind": 5, "location": {"uri": "file:///lib.rs",
+             "range": {"start": {"line": 0, "character": 0}, "end": {"line": 9, "character": 1}}}},
+            {"name": "main", "kind": 12, "location": {"uri": "file:///lib.rs",
+             "range": {"start": {"line": 11, "character": 0}, "end": {"line": 13, "character": 1}}}}
+        ]"#)
+            .unwrap()
+    }
+
+    #[test]
+    fn flat_symbols_are_nested_by_range() {
+        let tree = OutlineTree::new(flat_symbols());
+
+        assert_eq!(tree.roots().len(), 2);
+        let helper = tree.enclosing(&Position { line: 4, character: 0 }).unwrap();
+        assert_eq!(tree.node(helper).symbol.name, "helper");
+        let names: Vec<&str> = tree.path(helper)
+            .into_iter()
+            .map(|id| tree.node(id).symbol.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Parser", "helper"]);
+        assert_eq!(tree.enclosing(&Position { line: 10, character: 0 }), None);
+    }
+
+    #[test]
+    fn outline_can_be_flattened() {
+        let flat = OutlineTree::new(flat_symbols()).flatten("file:///lib.rs");
+
+        assert_eq!(flat.len(), 3);
+        assert_eq!(flat[1].name, "helper");
+        assert_eq!(flat[1].container_name, Some("Parser".to_string()));
+    }
+}
@@ -0,0 +1,283 @@
+//! Type hierarchies, and a walker collecting the inheritance graph around a type.
+use call_hierarchy::ItemKey;
+use client::RpcClient;
+use error::Error;
+use futures::Future;
+use futures::future::{self, Either, Loop};
+use languageserver_types::{Range, SymbolKind};
+use messages::ResponseError;
+use serde_json as json;
+use std::collections::{HashMap, HashSet};
+use utils::call_with_params;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TypeHierarchyItem {
+    pub name: String,
+    pub kind: SymbolKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: String,
+    pub range: Range,
+    #[serde(rename = "selectionRange")]
+    pub selection_range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<json::Value>,
+}
+
+impl<'a> From<&'a TypeHierarchyItem> for ItemKey {
+    fn from(item: &'a TypeHierarchyItem) -> Self {
+        ItemKey::new(&item.uri, &item.selection_range, &item.name)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TypeHierarchySupertypesParams {
+    pub item: TypeHierarchyItem,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TypeHierarchySubtypesParams {
+    pub item: TypeHierarchyItem,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TypeHierarchyDirection {
+    Supertypes,
+    Subtypes,
+    /// Supertypes of the root and their own supertypes, and subtypes of the root and their own
+    /// subtypes. Siblings are not included.
+    Both,
+}
+
+/// The inheritance graph around a type, as an adjacency structure. Node `0` is the type the walk
+/// started from.
+pub struct TypeGraph {
+    items: Vec<TypeHierarchyItem>,
+    ids: HashMap<ItemKey, usize>,
+    supertypes: Vec<Vec<usize>>,
+    subtypes: Vec<Vec<usize>>,
+    truncated: HashSet<usize>,
+}
+
+impl TypeGraph {
+    pub fn new(root: TypeHierarchyItem) -> Self {
+        let mut graph = TypeGraph {
+            items: Vec::new(),
+            ids: HashMap::new(),
+            supertypes: Vec::new(),
+            subtypes: Vec::new(),
+            truncated: HashSet::new(),
+        };
+        graph.insert(root);
+        graph
+    }
+
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn item(&self, id: usize) -> &TypeHierarchyItem {
+        &self.items[id]
+    }
+
+    pub fn supertypes(&self, id: usize) -> &[usize] {
+        &self.supertypes[id]
+    }
+
+    pub fn subtypes(&self, id: usize) -> &[usize] {
+        &self.subtypes[id]
+    }
+
+    /// Whether the node was not explored further because the depth limit was reached.
+    pub fn is_truncated(&self, id: usize) -> bool {
+        self.truncated.contains(&id)
+    }
+
+    /// All the `(subtype, supertype)` edges.
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        self.supertypes
+            .iter()
+            .enumerate()
+            .flat_map(|(subtype, supertypes)| supertypes.iter().map(move |&supertype| (subtype, supertype)))
+            .collect()
+    }
+
+    /// Add an item, unless it is already in the graph. Returns its id and whether it is new.
+    pub fn insert(&mut self, item: TypeHierarchyItem) -> (usize, bool) {
+        let key = ItemKey::from(&item);
+        if let Some(&id) = self.ids.get(&key) {
+            return (id, false);
+        }
+        let id = self.items.len();
+        self.items.push(item);
+        self.ids.insert(key, id);
+        self.supertypes.push(Vec::new());
+        self.subtypes.push(Vec::new());
+        (id, true)
+    }
+
+    pub fn add_edge(&mut self, subtype: usize, supertype: usize) {
+        if !self.supertypes[subtype].contains(&supertype) {
+            self.supertypes[subtype].push(supertype);
+            self.subtypes[supertype].push(subtype);
+        }
+    }
+}
+
+/// Walk the hierarchy breadth-first from `root`, at most `max_depth` levels away from it.
+pub fn walk(client: RpcClient, root: TypeHierarchyItem, direction: TypeHierarchyDirection, max_depth: usize) -> impl 'static + Future<Item=Result<TypeGraph, ResponseError<()>>, Error=Error>
+{
+    let mut client = client;
+    walk_with(root, direction, max_depth, move |item, direction| related_types(&mut client, item, direction))
+}
+
+/// Like `walk`, getting the supertypes or subtypes of an item with `related`.
+fn walk_with<F, R>(root: TypeHierarchyItem, direction: TypeHierarchyDirection, max_depth: usize, related: F) -> impl 'static + Future<Item=Result<TypeGraph, ResponseError<()>>, Error=Error>
+    where F: 'static + FnMut(&TypeHierarchyItem, TypeHierarchyDirection) -> R,
+          R: 'static + Future<Item=Result<Option<Vec<TypeHierarchyItem>>, ResponseError<()>>, Error=Error>
+{
+    let graph = TypeGraph::new(root);
+    let frontier = vec![(graph.root(), direction)];
+
+    future::loop_fn((related, graph, frontier, 0), move |(mut related, mut graph, frontier, depth)| {
+        if frontier.is_empty() {
+            return Either::A(future::ok(Loop::Break(Ok(graph))));
+        }
+        if depth >= max_depth {
+            graph.truncated.extend(frontier.into_iter().map(|(id, _)| id));
+            return Either::A(future::ok(Loop::Break(Ok(graph))));
+        }
+
+        let mut requests = Vec::new();
+        for (id, direction) in frontier {
+            if direction != TypeHierarchyDirection::Subtypes {
+                let supertypes = related(graph.item(id), TypeHierarchyDirection::Supertypes);
+                requests.push(supertypes.map(move |result| (id, TypeHierarchyDirection::Supertypes, result)));
+            }
+            if direction != TypeHierarchyDirection::Supertypes {
+                let subtypes = related(graph.item(id), TypeHierarchyDirection::Subtypes);
+                requests.push(subtypes.map(move |result| (id, TypeHierarchyDirection::Subtypes, result)));
+            }
+        }
+
+        Either::B(future::join_all(requests).map(move |responses| {
+            let mut next = Vec::new();
+            for (id, direction, result) in responses {
+                let items = match result {
+                    Ok(items) => items.unwrap_or_default(),
+                    Err(err) => return Loop::Break(Err(err)),
+                };
+                for item in items {
+                    let (related, is_new) = graph.insert(item);
+                    if direction == TypeHierarchyDirection::Supertypes {
+                        graph.add_edge(id, related);
+                    } else {
+                        graph.add_edge(related, id);
+                    }
+                    if is_new {
+                        next.push((related, direction));
+                    }
+                }
+            }
+            Loop::Continue((related, graph, next, depth + 1))
+        }))
+    })
+}
+
+fn related_types(client: &mut RpcClient, item: &TypeHierarchyItem, direction: TypeHierarchyDirection) -> impl 'static + Future<Item=Result<Option<Vec<TypeHierarchyItem>>, ResponseError<()>>, Error=Error>
+{
+    let method = match direction {
+        TypeHierarchyDirection::Supertypes => "typeHierarchy/supertypes",
+        _ => "typeHierarchy/subtypes",
+    };
+    // Both requests take the same params.
+    let params = TypeHierarchySupertypesParams { item: item.clone() };
+    call_with_params(client, method, params)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use languageserver_types::{Position, Range, SymbolKind};
+
+    fn item(name: &str, line: u64) -> TypeHierarchyItem {
+        let range = Range {
+            start: Position { line: line, character: 0 },
+            end: Position { line: line, character: 10 },
+        };
+        TypeHierarchyItem {
+            name: name.to_string(),
+            kind: SymbolKind::Class,
+            detail: None,
+            uri: "file:///Shapes.java".to_string(),
+            range: range.clone(),
+            selection_range: range,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn type_graph_deduplicates_items_and_edges() {
+        let mut graph = TypeGraph::new(item("Square", 10));
+        let (rectangle, is_new) = graph.insert(item("Rectangle", 5));
+        assert!(is_new);
+        let root = graph.root();
+        graph.add_edge(root, rectangle);
+        graph.add_edge(root, rectangle);
+
+        let (shape, _) = graph.insert(item("Shape", 1));
+        graph.add_edge(rectangle, shape);
+        assert_eq!(graph.insert(item("Rectangle", 5)), (rectangle, false));
+
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.supertypes(root), &[rectangle]);
+        assert_eq!(graph.subtypes(shape), &[rectangle]);
+        assert_eq!(graph.edges(), vec![(0, rectangle), (rectangle, shape)]);
+    }
+
+    /// Walks a hierarchy given as `(subtype, supertype)` names, from `root`.
+    fn walk_names(edges: Vec<(&'static str, &'static str)>, root: &str, direction: TypeHierarchyDirection, max_depth: usize) -> TypeGraph {
+        let walked = walk_with(item(root, 0), direction, max_depth, move |current, direction| {
+            let related: Vec<_> = edges.iter()
+                .filter_map(|&(subtype, supertype)| match direction {
+                    TypeHierarchyDirection::Supertypes if subtype == current.name => Some(supertype),
+                    TypeHierarchyDirection::Subtypes if supertype == current.name => Some(subtype),
+                    _ => None,
+                })
+                .map(|name| item(name, name.len() as u64))
+                .collect();
+            future::ok(Ok(Some(related)))
+        });
+        walked.wait().unwrap().unwrap()
+    }
+
+    fn names(graph: &TypeGraph) -> Vec<&str> {
+        (0..graph.len()).map(|id| graph.item(id).name.as_str()).collect()
+    }
+
+    #[test]
+    fn walks_stop_at_the_depth_limit() {
+        let edges = vec![("Square", "Rectangle"), ("Rectangle", "Polygon"), ("Polygon", "Shape")];
+        let graph = walk_names(edges.clone(), "Square", TypeHierarchyDirection::Supertypes, 2);
+        assert_eq!(names(&graph), vec!["Square", "Rectangle", "Polygon"]);
+        assert!(graph.is_truncated(2));
+        assert!(!graph.is_truncated(1));
+
+        let graph = walk_names(edges, "Polygon", TypeHierarchyDirection::Both, 1);
+        assert_eq!(names(&graph), vec!["Polygon", "Shape", "Rectangle"]);
+        assert_eq!(graph.edges(), vec![(0, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn walks_terminate_on_cycles() {
+        let edges = vec![("Square", "Rectangle"), ("Rectangle", "Shape"), ("Shape", "Square")];
+        let graph = walk_names(edges, "Square", TypeHierarchyDirection::Supertypes, 10);
+        assert_eq!(names(&graph), vec!["Square", "Rectangle", "Shape"]);
+        assert_eq!(graph.edges(), vec![(0, 1), (1, 2), (2, 0)]);
+        assert!((0..graph.len()).all(|id| !graph.is_truncated(id)));
+    }
+}
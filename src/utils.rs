@@ -1,7 +1,11 @@
-use messages::ResponseMessage;
-use serde_json::from_value;
-use serde::Deserialize;
+use client::RpcClient;
+use futures::Future;
+use messages::{RequestMessage, ResponseMessage};
+use serde_json as json;
+use serde_json::{from_value, Value};
+use serde::{de, Deserialize, Serialize};
 use error::Error;
+use tokio_service::Service;
 
 pub fn handle_response<R, E>(response: ResponseMessage) -> Result<Result<R, E>, Error>
     where R: Deserialize,
@@ -10,6 +14,25 @@ pub fn handle_response<R, E>(response: ResponseMessage) -> Result<Result<R, E>,
     match (response.result, response.error) {
         (Some(result), None) => Ok(Ok(from_value::<R>(result)?)),
         (None, Some(error)) => Ok(Err(from_value::<E>(error)?)),
+        // A `null` result is deserialized as a missing one.
+        (None, None) => Ok(Ok(from_value::<R>(Value::Null)?)),
         _ => Err(Error::OOL),
     }
 }
+
+pub fn call_with_params<'a, REQ, RES, ERR>(client: &mut RpcClient, method: &str, params: REQ) -> impl 'a + Future<Item=Result<RES, ERR>, Error=Error>
+    where RES: Deserialize + 'static,
+          ERR: Deserialize + 'static,
+          REQ: Serialize
+{
+    client.call(RequestMessage::new(method.to_string(), json::to_value(params)))
+        .then(|res| handle_response(res?))
+}
+
+/// Deserialize a JSON value from within a `Deserialize` implementation.
+pub fn from_json<T, E>(value: Value) -> Result<T, E>
+    where T: Deserialize,
+          E: de::Error
+{
+    from_value(value).map_err(|err| E::custom(err.to_string()))
+}
@@ -0,0 +1,387 @@
+//! A pool of servers, one per language and project root, started when the first file they
+//! handle is opened. Notifications and requests about a document go to the server it was
+//! opened on.
+use error::{Error, Result};
+use futures::Future;
+use futures::future::{self, Either, Shared, SharedError};
+use language::Language;
+use roots;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use supervisor::{RestartPolicy, Supervisor};
+use tokio_core::reactor::Handle;
+use types::*;
+use url::Url;
+use LanguageServer;
+
+type Initialization = Shared<Box<Future<Item = (), Error = Error>>>;
+
+struct PooledServer {
+    /// The index of the language in `Workspace::languages`.
+    language: usize,
+    server: Rc<RefCell<LanguageServer>>,
+    /// Resolves once the server answered the initialize request, and was sent `initialized`.
+    initialization: Initialization,
+    /// Whether the initialization failed, in which case no more files are opened on the server.
+    failed: Rc<Cell<bool>>,
+    supervisor: Option<Supervisor>,
+}
+
+pub struct Workspace {
+    handle: Handle,
+    languages: Vec<Rc<Language>>,
+    servers: Vec<PooledServer>,
+    /// The index of the server each open document was opened on, by URI.
+    documents: Rc<RefCell<HashMap<String, usize>>>,
+    /// How the servers started from now on are restarted when they crash, if they are.
+    restart_policy: Option<RestartPolicy>,
+}
+
+impl Workspace {
+    pub fn new(handle: Handle) -> Self {
+        Workspace {
+            handle: handle,
+            languages: Vec::new(),
+            servers: Vec::new(),
+            documents: Rc::new(RefCell::new(HashMap::new())),
+            restart_policy: None,
+        }
+    }
+
+    /// Adds a language to the workspace. When several languages handle a file, the first one
+    /// registered is used.
+    pub fn register<L: Language + 'static>(&mut self, language: L) {
+        self.languages.push(Rc::new(language));
+    }
+
+    /// Restarts the servers started from now on when they crash. See `Supervisor`.
+    pub fn supervise(&mut self, policy: RestartPolicy) {
+        self.restart_policy = Some(policy);
+    }
+
+    /// The servers started so far.
+    pub fn servers(&self) -> Vec<Rc<RefCell<LanguageServer>>> {
+        self.servers.iter().map(|pooled| pooled.server.clone()).collect()
+    }
+
+    /// The server a document was opened on.
+    pub fn server_for(&self, uri: &str) -> Option<Rc<RefCell<LanguageServer>>> {
+        self.documents.borrow().get(uri).map(|&index| self.servers[index].server.clone())
+    }
+
+    /// Opens a file on the server of its language and project. An initialized server is reused
+    /// if the file is in one of its workspace folders, or if it supports several of them, in
+    /// which case the project root of the file is added. Otherwise a server is started for the
+    /// project, and initialized before the file is opened. Opening a file twice does nothing,
+    /// unless opening it failed the first time. Servers that failed to initialize are not
+    /// reused: another one is started.
+    pub fn open(&mut self, path: &Path) -> Box<Future<Item = (), Error = Error>> {
+        let language = match self.languages.iter().position(|language| language.language_id(path).is_some()) {
+            Some(language) => language,
+            None => return Box::new(future::err(Error::UnknownLanguage(path.to_string_lossy().into_owned()))),
+        };
+        let uri = match Url::from_file_path(path) {
+            Ok(uri) => uri.as_str().to_string(),
+            Err(_) => return Box::new(future::err(Error::InvalidUri(path.to_string_lossy().into_owned()))),
+        };
+        if self.documents.borrow().contains_key(&uri) {
+            return Box::new(future::ok(()));
+        }
+
+        let root = roots::root_or_parent(path, &self.languages[language].root_markers());
+        let (index, new_folder) = match self.find_server(language, path) {
+            Some(found) => found,
+            None => {
+                match self.start(language, &root) {
+                    Ok(index) => (index, false),
+                    Err(err) => return Box::new(future::err(err)),
+                }
+            }
+        };
+        // The document is recorded right away, so that it is not opened twice while the server
+        // initializes, and forgotten if it could not be opened.
+        self.documents.borrow_mut().insert(uri.clone(), index);
+        let documents = self.documents.clone();
+
+        let server = self.servers[index].server.clone();
+        let path = path.to_path_buf();
+        Box::new(self.initialized(index)
+            .and_then(move |_| {
+                let added = if new_folder {
+                    Either::A(server.borrow().add_workspace_folder(&root))
+                } else {
+                    Either::B(future::ok(()))
+                };
+                added.and_then(move |_| {
+                    let opened = server.borrow().open_file(&path);
+                    opened
+                })
+            })
+            .map_err(move |err| {
+                documents.borrow_mut().remove(&uri);
+                err
+            }))
+    }
+
+    /// The server to reuse for a file, and whether the project root of the file has to be
+    /// added to its workspace folders.
+    fn find_server(&self, language: usize, path: &Path) -> Option<(usize, bool)> {
+        let candidates = || {
+            self.servers
+                .iter()
+                .enumerate()
+                .filter(move |&(_, pooled)| pooled.language == language && !pooled.failed.get())
+        };
+        if let Some((index, _)) = candidates().find(|&(_, pooled)| pooled.server.borrow().can_handle(path)) {
+            return Some((index, false));
+        }
+        candidates()
+            .find(|&(_, pooled)| pooled.server.borrow().supports_workspace_folders())
+            .map(|(index, _)| (index, true))
+    }
+
+    fn start(&mut self, language: usize, root: &Path) -> Result<usize> {
+        let mut server = LanguageServer::spawn(self.languages[language].clone(),
+                                               Some(root.to_path_buf()),
+                                               self.handle.clone())?;
+        let initialize = server.initialize_root(None);
+        let server = Rc::new(RefCell::new(server));
+        let initialized = server.clone();
+        let failed = Rc::new(Cell::new(false));
+        let failure = failed.clone();
+        let initialization: Box<Future<Item = (), Error = Error>> = Box::new(initialize.and_then(move |result| {
+                match result {
+                    Ok(_) => {
+                        let notified = initialized.borrow().initialized();
+                        Either::A(notified)
+                    }
+                    Err(err) => Either::B(future::err(Error::InitializeFailed(err.message().to_string()))),
+                }
+            })
+            .map_err(move |err| {
+                failure.set(true);
+                err
+            }));
+        let supervisor = self.restart_policy
+            .clone()
+            .map(|policy| Supervisor::for_shared(server.clone(), policy));
+        self.servers.push(PooledServer {
+            language: language,
+            server: server,
+            initialization: initialization.shared(),
+            failed: failed,
+            supervisor: supervisor,
+        });
+        Ok(self.servers.len() - 1)
+    }
+
+    fn initialized(&self, index: usize) -> impl 'static + Future<Item = (), Error = Error> {
+        self.servers[index].initialization.clone().map(|_| ()).map_err(initialization_error)
+    }
+
+    /// Runs a request on the server a document was opened on, once it is initialized.
+    pub fn request<F, R>(&self, uri: &str, request: F) -> Box<Future<Item = R::Item, Error = Error>>
+        where F: 'static + FnOnce(&mut LanguageServer) -> R,
+              R: 'static + Future<Error = Error>
+    {
+        let index = match self.documents.borrow().get(uri) {
+            Some(&index) => index,
+            None => return Box::new(future::err(Error::DocumentNotOpen(uri.to_string()))),
+        };
+        let server = self.servers[index].server.clone();
+        Box::new(self.initialized(index).and_then(move |_| {
+            let requested = request(&mut server.borrow_mut());
+            requested
+        }))
+    }
+
+    pub fn did_change_text_document(&self, params: DidChangeTextDocumentParams) -> Box<Future<Item = (), Error = Error>> {
+        let uri = params.text_document.uri.to_string();
+        self.request(&uri, move |server| server.did_change_text_document(params))
+    }
+
+    pub fn did_save_text_document(&self, params: DidSaveTextDocumentParams) -> Box<Future<Item = (), Error = Error>> {
+        let uri = params.text_document.uri.to_string();
+        self.request(&uri, move |server| server.did_save_text_document(params))
+    }
+
+    /// Closes a document on its server. The server keeps running. The document stays open if
+    /// the server could not be told, so that closing it can be retried.
+    pub fn did_close_text_document(&mut self, params: DidCloseTextDocumentParams) -> Box<Future<Item = (), Error = Error>> {
+        let uri = params.text_document.uri.to_string();
+        let documents = self.documents.clone();
+        Box::new(self.request(&uri, move |server| server.did_close_text_document(params))
+            .map(move |_| {
+                documents.borrow_mut().remove(&uri);
+            }))
+    }
+
+    /// Shuts all the servers down and tells them to exit, including the ones that failed to
+    /// initialize. The workspace is empty afterwards, and can start servers again.
+    pub fn shutdown(&mut self) -> impl 'static + Future<Item = (), Error = Error> {
+        self.documents.borrow_mut().clear();
+        let shutdowns: Vec<_> = self.servers
+            .drain(..)
+            .map(|pooled| {
+                if let Some(ref supervisor) = pooled.supervisor {
+                    supervisor.stop();
+                }
+                let server = pooled.server;
+                pooled.initialization.then(move |_| {
+                    let shutdown = server.borrow_mut().shutdown(());
+                    shutdown.and_then(move |_| {
+                        let exited = server.borrow().exit(());
+                        exited
+                    })
+                })
+            })
+            .collect();
+        future::join_all(shutdowns).map(|_| ())
+    }
+}
+
+/// The initialization is shared by the requests waiting on it, so its error cannot be moved
+/// out: build an equivalent one.
+fn initialization_error(err: SharedError<Error>) -> Error {
+    match *err {
+        Error::InitializeFailed(ref message) => Error::InitializeFailed(message.clone()),
+        ref err => Error::InitializeFailed(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use language::Language;
+    use serde_json as json;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    struct Cat(&'static str);
+
+    impl Language for Cat {
+        fn get_command(&self) -> Vec<String> {
+            vec!["cat".to_string()]
+        }
+
+        fn language_ids(&self) -> Vec<(String, String)> {
+            vec![(self.0.to_string(), self.0.to_string())]
+        }
+    }
+
+    fn add_server(workspace: &mut Workspace, language: usize, root: &Path, initialization: Result<()>) -> usize {
+        let server = LanguageServer::spawn(workspace.languages[language].clone(),
+                                           Some(root.to_path_buf()),
+                                           workspace.handle.clone())
+            .unwrap();
+        let initialization: Box<Future<Item = (), Error = Error>> = Box::new(future::result(initialization));
+        workspace.servers.push(PooledServer {
+            language: language,
+            server: Rc::new(RefCell::new(server)),
+            initialization: initialization.shared(),
+            failed: Rc::new(Cell::new(false)),
+            supervisor: None,
+        });
+        workspace.servers.len() - 1
+    }
+
+    fn temp_dirs(names: &[&str]) -> (PathBuf, Vec<PathBuf>) {
+        let base = env::temp_dir().join(Uuid::new_v4().to_string());
+        let dirs = names.iter().map(|name| base.join(name)).collect::<Vec<_>>();
+        for dir in &dirs {
+            fs::create_dir_all(dir).unwrap();
+        }
+        (base, dirs)
+    }
+
+    fn close_params(uri: &str) -> DidCloseTextDocumentParams {
+        json::from_str(&format!(r#"{{"textDocument": {{"uri": "{}"}}}}"#, uri)).unwrap()
+    }
+
+    #[test]
+    fn files_go_to_the_server_of_their_folder_or_one_supporting_several() {
+        let core = Core::new().unwrap();
+        let mut workspace = Workspace::new(core.handle());
+        workspace.register(Cat("rs"));
+        workspace.register(Cat("go"));
+        let (base, dirs) = temp_dirs(&["a", "b", "c"]);
+
+        let go = add_server(&mut workspace, 1, &dirs[0], Ok(()));
+        let single = add_server(&mut workspace, 0, &dirs[0], Ok(()));
+        assert_eq!(workspace.find_server(0, &dirs[0].join("main.rs")), Some((single, false)));
+        assert_eq!(workspace.find_server(1, &dirs[0].join("main.go")), Some((go, false)));
+        assert_eq!(workspace.find_server(0, &dirs[1].join("main.rs")), None);
+
+        let several = add_server(&mut workspace, 0, &dirs[1], Ok(()));
+        workspace.servers[several].server.borrow().capabilities.set(Some(json::from_str(r#"{
+            "workspace": {"workspaceFolders": {"supported": true, "changeNotifications": true}}
+        }"#).unwrap()));
+        assert_eq!(workspace.find_server(0, &dirs[1].join("main.rs")), Some((several, false)));
+        assert_eq!(workspace.find_server(0, &dirs[2].join("main.rs")), Some((several, true)));
+        assert_eq!(workspace.find_server(1, &dirs[2].join("main.go")), None);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn requests_about_documents_not_open_fail() {
+        let mut core = Core::new().unwrap();
+        let mut workspace = Workspace::new(core.handle());
+
+        match core.run(workspace.request("file:///main.rs", |server| server.initialized())) {
+            Err(Error::DocumentNotOpen(uri)) => assert_eq!(uri, "file:///main.rs"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match core.run(workspace.did_close_text_document(close_params("file:///main.rs"))) {
+            Err(Error::DocumentNotOpen(_)) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn documents_failing_to_open_are_forgotten() {
+        let mut core = Core::new().unwrap();
+        let mut workspace = Workspace::new(core.handle());
+        workspace.register(Cat("rs"));
+        let (base, dirs) = temp_dirs(&["a"]);
+        let index = add_server(&mut workspace, 0, &dirs[0], Err(Error::InitializeFailed("crashed".to_string())));
+        let file = dirs[0].join("main.rs");
+        let uri = Url::from_file_path(&file).unwrap().to_string();
+
+        match core.run(workspace.open(&file)) {
+            Err(Error::InitializeFailed(message)) => assert_eq!(message, "crashed"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(workspace.server_for(&uri).is_none());
+
+        // Servers that failed to initialize are not reused.
+        workspace.servers[index].failed.set(true);
+        assert_eq!(workspace.find_server(0, &file), None);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn documents_stay_open_when_closing_them_fails() {
+        let mut core = Core::new().unwrap();
+        let mut workspace = Workspace::new(core.handle());
+        workspace.register(Cat("rs"));
+        let (base, dirs) = temp_dirs(&["a"]);
+        let index = add_server(&mut workspace, 0, &dirs[0], Err(Error::InitializeFailed("crashed".to_string())));
+        let uri = Url::from_file_path(dirs[0].join("main.rs")).unwrap().to_string();
+        workspace.documents.borrow_mut().insert(uri.clone(), index);
+
+        match core.run(workspace.did_close_text_document(close_params(&uri))) {
+            Err(Error::InitializeFailed(message)) => assert_eq!(message, "crashed"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(workspace.server_for(&uri).is_some());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}